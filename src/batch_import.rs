@@ -0,0 +1,338 @@
+// running laz-import over a whole delivery of tiles at once,
+// either one output per tile or everything merged into a single file
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use arrow_array::RecordBatch;
+use rayon::prelude::*;
+
+use crate::error::{QuafferError, Result};
+use crate::extra_bytes::merge_descriptors;
+use crate::laz_to_gpq::{
+    GpqWriter, ImportOptions, ImportSummary, LazTile, OutputLayout, PointAttributes,
+    WKTStringTransform, check_options, read_laz_to_gpq,
+};
+use crate::point_source::SourceFormat;
+
+// how many finished batches can pile up waiting on the merged writer
+// before the tile readers block (keeps memory bounded when merging)
+const MERGE_QUEUE_DEPTH: usize = 4;
+
+type TileResult = (String, Result<ImportSummary>);
+
+// with --input-format only files of that format get picked out of directories
+fn is_point_cloud(path: &Path, format: Option<SourceFormat>) -> bool {
+    match (SourceFormat::from_path(path), format) {
+        (Some(found), Some(format)) => found == format,
+        (found, None) => found.is_some(),
+        // e.g. .txt files, which only count when asked for
+        (None, Some(format)) => format == SourceFormat::Csv && has_extension(path, "txt"),
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+// turn a mix of files, directories and glob patterns into a sorted list of tiles
+pub fn expand_inputs(inputs: &[String], format: Option<SourceFormat>) -> Result<Vec<PathBuf>> {
+    let mut tiles = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_file() && is_point_cloud(&entry_path, format) {
+                    tiles.push(entry_path);
+                }
+            }
+        } else if path.exists() {
+            tiles.push(path.to_path_buf());
+        } else {
+            let matches = glob::glob(input).map_err(|err| {
+                QuafferError::InvalidInput(format!("{input} isn't a file or a valid glob: {err}"))
+            })?;
+            let found_before = tiles.len();
+            for matched in matches {
+                let matched = matched.map_err(|err| QuafferError::Io(err.into_error()))?;
+                if matched.is_file() {
+                    tiles.push(matched);
+                }
+            }
+            if tiles.len() == found_before {
+                return Err(QuafferError::InvalidInput(format!(
+                    "{input} didn't match any files"
+                )));
+            }
+        }
+    }
+    tiles.sort();
+    tiles.dedup();
+    if tiles.is_empty() {
+        return Err(QuafferError::InvalidInput(
+            "no point cloud files found to import".to_string(),
+        ));
+    }
+    Ok(tiles)
+}
+
+// "tile.LAZ" -> "tile.parquet" (or "tile_filter.parquet" when points are filtered or clipped)
+pub fn output_file_name(input: &Path, filtered: bool) -> String {
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let suffix = if filtered { "_filter" } else { "" };
+    format!("{stem}{suffix}.parquet")
+}
+
+// where each tile's geoparquet ends up when it isn't merged: next to the input by default,
+// or inside `output` (a directory, or a file path when there's only one tile)
+fn plan_outputs(tiles: &[PathBuf], output: Option<&Path>, filtered: bool) -> Result<Vec<PathBuf>> {
+    let output_is_dir = output.is_some_and(|output| {
+        output.is_dir()
+            || output
+                .to_string_lossy()
+                .ends_with(['/', std::path::MAIN_SEPARATOR])
+            || tiles.len() > 1
+    });
+    if let Some(output) = output
+        && output_is_dir
+    {
+        std::fs::create_dir_all(output)?;
+    }
+
+    let outputs: Vec<PathBuf> = tiles
+        .iter()
+        .map(|tile_path| {
+            let file_name = output_file_name(tile_path, filtered);
+            match output {
+                Some(dir) if output_is_dir => dir.join(file_name),
+                Some(file) => file.to_path_buf(),
+                None => tile_path.with_file_name(file_name),
+            }
+        })
+        .collect();
+
+    // e.g. "a.laz" and "a.las" in the same directory would stomp on each other
+    let mut sorted_outputs: Vec<(&PathBuf, &PathBuf)> = outputs.iter().zip(tiles).collect();
+    sorted_outputs.sort();
+    for pair in sorted_outputs.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            return Err(QuafferError::InvalidInput(format!(
+                "{} and {} would both be written to {}",
+                pair[0].1.display(),
+                pair[1].1.display(),
+                pair[0].0.display()
+            )));
+        }
+    }
+    Ok(outputs)
+}
+
+// convert every tile to its own geoparquet file, spread across all cores
+fn import_each(
+    tiles: &[PathBuf],
+    outputs: &[PathBuf],
+    import_options: &ImportOptions,
+) -> Vec<TileResult> {
+    tiles
+        .par_iter()
+        .zip(outputs.par_iter())
+        .map(|(tile_path, outfile_path)| {
+            let input = tile_path.to_string_lossy().to_string();
+            let outfile_path = outfile_path.to_string_lossy().to_string();
+            let result = read_laz_to_gpq(input.clone(), outfile_path, import_options);
+            (input, result)
+        })
+        .collect()
+}
+
+// read tiles in parallel and funnel their batches into one geoparquet file
+fn import_merged(
+    tiles: &[PathBuf],
+    outfile_path: &str,
+    import_options: &ImportOptions,
+) -> Result<Vec<TileResult>> {
+    // every tile has to land in the same CRS for a merged file to make sense,
+    // so peek at all the headers up front (also gives us non-overlapping fids)
+    let mut tile_crses: Vec<(String, Option<WKTStringTransform>)> = Vec::new();
+    let mut fid_offsets = Vec::with_capacity(tiles.len());
+    let mut next_fid: i64 = 0;
+    let mut attributes = PointAttributes::default();
+    let mut extra_bytes = Vec::new();
+    let mut source_headers = Vec::with_capacity(tiles.len());
+    for tile_path in tiles {
+        let input = tile_path.to_string_lossy().to_string();
+        let tile = LazTile::open(&input, import_options)?;
+        fid_offsets.push(next_fid);
+        next_fid += tile.number_of_points() as i64;
+        attributes = attributes.union(tile.attributes());
+        merge_descriptors(&mut extra_bytes, &tile.extra_bytes)?;
+        source_headers.extend(tile.header_json());
+        tile_crses.push((input, tile.output_crs.clone()));
+    }
+    let label = |crs: &Option<WKTStringTransform>| {
+        crs.as_ref()
+            .map(|crs| crs.label())
+            .unwrap_or_else(|| "no CRS".to_string())
+    };
+    let (first_input, output_crs) = &tile_crses[0];
+    for (input, crs) in &tile_crses[1..] {
+        let same = match (output_crs, crs) {
+            (Some(a), Some(b)) => a.is_same(b),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            return Err(QuafferError::Crs(format!(
+                "can't merge {input} ({}) with {first_input} ({}), \
+                pass --target-crs to put them in the same CRS",
+                label(crs),
+                label(output_crs)
+            )));
+        }
+    }
+
+    let layout = OutputLayout::new(import_options, attributes, extra_bytes);
+    println!("Merging {} tiles into {outfile_path}...", tiles.len());
+    let mut writer = GpqWriter::try_new(
+        outfile_path,
+        layout.clone(),
+        output_crs.as_ref(),
+        import_options,
+    )?;
+    for header in source_headers {
+        writer.add_source_header(header);
+    }
+
+    let (sender, receiver) = mpsc::sync_channel::<RecordBatch>(MERGE_QUEUE_DEPTH);
+    let results = std::thread::scope(|scope| -> Result<Vec<TileResult>> {
+        let producer = scope.spawn(move || {
+            tiles
+                .par_iter()
+                .zip(fid_offsets.par_iter())
+                .map_with(sender, |sender, (tile_path, fid_offset)| {
+                    let input = tile_path.to_string_lossy().to_string();
+                    let result = LazTile::open(&input, import_options).and_then(|tile| {
+                        tile.stream_chunks(import_options, &layout, *fid_offset, |chunk| {
+                            let batch = chunk.drain_to_record_batch(&layout)?;
+                            sender.send(batch).map_err(|_| {
+                                QuafferError::InvalidInput(
+                                    "merged writer stopped taking batches".to_string(),
+                                )
+                            })
+                        })
+                    });
+                    (input, result)
+                })
+                .collect::<Vec<TileResult>>()
+        });
+        // the loop ends once every tile reader is done and has dropped its sender,
+        // bailing out early drops the receiver which unblocks (and fails) the readers
+        for batch in receiver {
+            writer.write_batch(&batch)?;
+        }
+        Ok(producer.join().expect("tile reader thread panicked"))
+    })?;
+    // a tile that failed partway already sent some of its batches, so finishing would
+    // leave a complete looking file with points missing. dropping the writer unfinished
+    // deletes the partial file instead (the failures show up in the summary)
+    if results.iter().any(|(_, result)| result.is_err()) {
+        println!("Some tiles failed, not writing {outfile_path}");
+        drop(writer);
+        return Ok(results);
+    }
+    writer.finish()?;
+    Ok(results)
+}
+
+fn print_summary(results: &[TileResult]) {
+    let header = [
+        "input",
+        "points read",
+        "points kept",
+        "CRS",
+        "bbox (minx, miny, maxx, maxy)",
+        "time",
+    ];
+    let rows: Vec<[String; 6]> = results
+        .iter()
+        .map(|(input, result)| match result {
+            Ok(summary) => [
+                input.clone(),
+                summary.points_read.to_string(),
+                summary.points_kept.to_string(),
+                summary.crs.clone().unwrap_or_else(|| "none".to_string()),
+                summary
+                    .bounds
+                    .map(|b| format!("{:.3}, {:.3}, {:.3}, {:.3}", b.minx, b.miny, b.maxx, b.maxy))
+                    .unwrap_or_else(|| "empty".to_string()),
+                format!("{:.1}s", summary.elapsed.as_secs_f64()),
+            ],
+            Err(err) => [
+                input.clone(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                format!("FAILED: {err}"),
+                "-".to_string(),
+            ],
+        })
+        .collect();
+
+    let mut widths = header.map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let print_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        println!("{}", line.join(" | "));
+    };
+    println!();
+    print_row(header.to_vec());
+    println!("{}", widths.map(|width| "-".repeat(width)).join("-+-"));
+    for row in &rows {
+        print_row(row.iter().map(|cell| cell.as_str()).collect());
+    }
+}
+
+// import any number of tiles, printing a summary table at the end
+// (tiles that fail don't stop the others, but do make the whole run fail)
+pub fn import_tiles(
+    inputs: &[String],
+    output: Option<&Path>,
+    merge: bool,
+    import_options: &ImportOptions,
+) -> Result<()> {
+    check_options(import_options)?;
+    let tiles = expand_inputs(inputs, import_options.source.format)?;
+    println!("Importing {} tile(s)...", tiles.len());
+    let results = if merge {
+        let outfile_path = output.filter(|output| !output.is_dir()).ok_or_else(|| {
+            QuafferError::InvalidInput("--merge needs an output file path".to_string())
+        })?;
+        import_merged(&tiles, &outfile_path.to_string_lossy(), import_options)?
+    } else {
+        let filtered = import_options.filter.is_active() || import_options.clip.is_some();
+        let outputs = plan_outputs(&tiles, output, filtered)?;
+        import_each(&tiles, &outputs, import_options)
+    };
+    print_summary(&results);
+
+    let failures = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failures > 0 {
+        return Err(QuafferError::InvalidInput(format!(
+            "{failures} of {} tiles failed to import",
+            results.len()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+// plain 2D bounds, shared by the importer (summaries, the bbox covering column)
+// and the reader (skipping row groups that can't match)
+use std::str::FromStr;
+
+use crate::error::QuafferError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bbox {
+    pub minx: f64,
+    pub miny: f64,
+    pub maxx: f64,
+    pub maxy: f64,
+}
+
+impl Bbox {
+    // grow `bbox` (if there is one yet) to cover x/y
+    pub fn extend(bbox: Option<Bbox>, x: f64, y: f64) -> Bbox {
+        match bbox {
+            Some(bbox) => Bbox {
+                minx: bbox.minx.min(x),
+                miny: bbox.miny.min(y),
+                maxx: bbox.maxx.max(x),
+                maxy: bbox.maxy.max(y),
+            },
+            None => Bbox {
+                minx: x,
+                miny: y,
+                maxx: x,
+                maxy: y,
+            },
+        }
+    }
+
+    // edges touching counts
+    pub fn intersects(&self, other: &Bbox) -> bool {
+        self.minx <= other.maxx
+            && self.maxx >= other.minx
+            && self.miny <= other.maxy
+            && self.maxy >= other.miny
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.minx && x <= self.maxx && y >= self.miny && y <= self.maxy
+    }
+}
+
+// "minx,miny,maxx,maxy"
+impl FromStr for Bbox {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_bbox = |reason: String| {
+            QuafferError::InvalidInput(format!(
+                "bbox `{s}` should look like minx,miny,maxx,maxy ({reason})"
+            ))
+        };
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|err| bad_bbox(err.to_string()))?;
+        let [minx, miny, maxx, maxy] = values[..] else {
+            return Err(bad_bbox(format!("got {} values", values.len())));
+        };
+        if minx >= maxx || miny >= maxy {
+            return Err(bad_bbox("min is not below max".to_string()));
+        }
+        Ok(Bbox {
+            minx,
+            miny,
+            maxx,
+            maxy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bboxes_parse() {
+        assert_eq!(
+            " 1, -2.5,3,4e2 ".parse::<Bbox>().unwrap(),
+            Bbox {
+                minx: 1.0,
+                miny: -2.5,
+                maxx: 3.0,
+                maxy: 400.0
+            }
+        );
+        for bad in ["1,2,3", "1,2,3,4,5", "1,2,x,4", "3,2,1,4", "1,4,3,4", ""] {
+            assert!(bad.parse::<Bbox>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn bboxes_grow_and_intersect() {
+        let bbox = Bbox::extend(Some(Bbox::extend(None, 1.0, 5.0)), 3.0, 2.0);
+        assert_eq!(bbox, "1,2,3,5".parse().unwrap());
+        assert!(bbox.contains(1.0, 5.0));
+        assert!(!bbox.contains(0.5, 3.0));
+        // touching edges count
+        assert!(bbox.intersects(&"3,5,4,6".parse().unwrap()));
+        assert!(!bbox.intersects(&"3.5,0,4,6".parse().unwrap()));
+    }
+}
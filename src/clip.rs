@@ -0,0 +1,177 @@
+// keeping only the points inside a bbox or polygon (e.g. one parcel out of a whole tile)
+use std::path::Path;
+
+use gdal::spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef};
+use geo::{BoundingRect, Coord, Geometry, Intersects, MapCoords, MultiPolygon, Polygon, Rect};
+use geozero::geojson::GeoJson;
+use geozero::wkt::WktStr;
+use geozero::{ToGeo, ToWkt};
+
+use crate::bbox::Bbox;
+use crate::error::{QuafferError, Result};
+use crate::laz_to_gpq::WKTStringTransform;
+
+// parquet footer key the clip geometry gets recorded under
+pub const CLIP_METADATA_KEY: &str = "point_quaffer:clip";
+
+// points put along each bbox edge, so the edges can bend if the bbox gets reprojected
+const BBOX_EDGE_POINTS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct ClipRegion {
+    pub shape: MultiPolygon<f64>,
+    // CRS the shape is given in, None means it's already in the CRS of the .laz points
+    pub crs: Option<String>,
+}
+
+fn densified_rect(rect: Rect<f64>) -> Polygon<f64> {
+    let (min, max) = (rect.min(), rect.max());
+    let corners = [
+        Coord { x: min.x, y: min.y },
+        Coord { x: max.x, y: min.y },
+        Coord { x: max.x, y: max.y },
+        Coord { x: min.x, y: max.y },
+    ];
+    let mut ring = Vec::with_capacity(corners.len() * BBOX_EDGE_POINTS + 1);
+    for (i, start) in corners.iter().enumerate() {
+        let end = corners[(i + 1) % corners.len()];
+        for step in 0..BBOX_EDGE_POINTS {
+            let t = step as f64 / BBOX_EDGE_POINTS as f64;
+            ring.push(Coord {
+                x: start.x + (end.x - start.x) * t,
+                y: start.y + (end.y - start.y) * t,
+            });
+        }
+    }
+    Polygon::new(ring.into(), vec![])
+}
+
+fn collect_polygons(geometry: Geometry<f64>, polygons: &mut Vec<Polygon<f64>>) {
+    match geometry {
+        Geometry::Polygon(polygon) => polygons.push(polygon),
+        Geometry::MultiPolygon(multi_polygon) => polygons.extend(multi_polygon),
+        Geometry::Rect(rect) => polygons.push(densified_rect(rect)),
+        Geometry::GeometryCollection(collection) => {
+            for geometry in collection {
+                collect_polygons(geometry, polygons);
+            }
+        }
+        // points and lines don't enclose anything
+        _ => {}
+    }
+}
+
+impl ClipRegion {
+    // "minx,miny,maxx,maxy"
+    pub fn from_bbox(bbox: &str, crs: Option<String>) -> Result<Self> {
+        let bbox: Bbox = bbox.parse()?;
+        let rect = Rect::new(
+            Coord {
+                x: bbox.minx,
+                y: bbox.miny,
+            },
+            Coord {
+                x: bbox.maxx,
+                y: bbox.maxy,
+            },
+        );
+        Ok(ClipRegion {
+            shape: MultiPolygon::new(vec![densified_rect(rect)]),
+            crs,
+        })
+    }
+
+    // a GeoJSON (geometry, feature or feature collection) or WKT file with polygons in it
+    pub fn from_file(path: &Path, crs: Option<String>) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let geometry = if contents.trim_start().starts_with('{') {
+            GeoJson(&contents).to_geo()
+        } else {
+            WktStr(contents.trim()).to_geo()
+        }
+        .map_err(|err| {
+            QuafferError::InvalidInput(format!(
+                "couldn't read a clip geometry from {}: {err}",
+                path.display()
+            ))
+        })?;
+        let mut polygons = Vec::new();
+        collect_polygons(geometry, &mut polygons);
+        if polygons.is_empty() {
+            return Err(QuafferError::InvalidInput(format!(
+                "{} doesn't have any polygons to clip with",
+                path.display()
+            )));
+        }
+        Ok(ClipRegion {
+            shape: MultiPolygon::new(polygons),
+            crs,
+        })
+    }
+
+    // what gets written to the output's file metadata
+    pub fn to_metadata_json(&self) -> Result<String> {
+        let wkt = Geometry::MultiPolygon(self.shape.clone())
+            .to_wkt()
+            .map_err(|err| QuafferError::InvalidInput(format!("couldn't write clip WKT: {err}")))?;
+        Ok(serde_json::json!({ "wkt": wkt, "crs": self.crs }).to_string())
+    }
+
+    // get the clip shape into the points' CRS and ready for point tests
+    pub fn prepare(&self, points_crs: Option<&WKTStringTransform>) -> Result<PreparedClip> {
+        let shape = match &self.crs {
+            None => self.shape.clone(),
+            Some(definition) => {
+                let points_crs = points_crs.ok_or_else(|| {
+                    QuafferError::Crs(format!(
+                        "clip geometry is in {definition} but the points have no CRS to put it in"
+                    ))
+                })?;
+                let mut clip_srs = SpatialRef::from_definition(definition)?;
+                let mut points_srs = points_crs.spatial_ref()?;
+                clip_srs.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+                points_srs.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+                let coord_transform = CoordTransform::new(&clip_srs, &points_srs)?;
+                let coord_transform = &coord_transform;
+                self.shape.try_map_coords(|coord| {
+                    let (mut x, mut y, mut z) = ([coord.x], [coord.y], [0.0]);
+                    coord_transform.transform_coords(&mut x, &mut y, &mut z)?;
+                    Ok::<_, gdal::errors::GdalError>(Coord { x: x[0], y: y[0] })
+                })?
+            }
+        };
+        let bounds = shape
+            .bounding_rect()
+            .ok_or_else(|| QuafferError::InvalidInput("clip geometry is empty".to_string()))?;
+        Ok(PreparedClip { bounds, shape })
+    }
+}
+
+// a clip shape in the same CRS as the points being tested
+#[derive(Debug, Clone)]
+pub struct PreparedClip {
+    bounds: Rect<f64>,
+    shape: MultiPolygon<f64>,
+}
+
+impl PreparedClip {
+    pub fn bounds(&self) -> Bbox {
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        Bbox {
+            minx: min.x,
+            miny: min.y,
+            maxx: max.x,
+            maxy: max.y,
+        }
+    }
+
+    // points on the boundary count as inside
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        // cheap bounds check first, most points in a tile are nowhere near a parcel
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        if x < min.x || x > max.x || y < min.y || y > max.y {
+            return false;
+        }
+        self.shape.intersects(&Coord { x, y })
+    }
+}
@@ -0,0 +1,101 @@
+// E57 scans (terrestrial scanners, mostly), every point cloud in the file in turn.
+// spherical coordinates and scan poses get turned into plain XYZ by the e57 crate
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use e57::{CartesianCoordinate, E57Reader, PointCloud};
+use gdal::spatial_ref::SpatialRef;
+use las::{Color, Point};
+
+use crate::error::Result;
+use crate::laz_to_gpq::{PointAttributes, WKTStringTransform};
+use crate::point_source::{PointSource, new_point};
+
+pub struct E57Source {
+    path: PathBuf,
+    reader: E57Reader<BufReader<File>>,
+    pointclouds: Vec<PointCloud>,
+}
+
+// intensity and color come out of the e57 crate as 0-1, LAS wants 16 bits
+fn to_u16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * f32::from(u16::MAX)).round() as u16
+}
+
+impl E57Source {
+    pub fn open(path: &Path) -> Result<Self> {
+        let reader = E57Reader::from_file(path)?;
+        let pointclouds = reader.pointclouds();
+        Ok(E57Source {
+            path: path.to_path_buf(),
+            reader,
+            pointclouds,
+        })
+    }
+}
+
+impl PointSource for E57Source {
+    // coordinate metadata is free text in the spec, but it's usually WKT or an EPSG code
+    fn crs(&self) -> Result<Option<WKTStringTransform>> {
+        let Some(definition) = self
+            .reader
+            .coordinate_metadata()
+            .map(str::trim)
+            .filter(|definition| !definition.is_empty())
+        else {
+            return Ok(None);
+        };
+        match SpatialRef::from_definition(definition) {
+            Ok(spatial_ref) => Ok(Some(WKTStringTransform::from_spatial_ref(&spatial_ref)?)),
+            Err(err) => {
+                eprintln!(
+                    "WARNING: {}: can't make a CRS out of the coordinate metadata ({err})",
+                    self.path.display()
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    fn number_of_points(&self) -> u64 {
+        self.pointclouds.iter().map(|pc| pc.records).sum()
+    }
+
+    fn attributes(&self) -> PointAttributes {
+        PointAttributes {
+            color: self.pointclouds.iter().any(|pc| pc.has_color()),
+            ..Default::default()
+        }
+    }
+
+    fn read_points(&mut self, on_point: &mut dyn FnMut(Point) -> Result<bool>) -> Result<()> {
+        for pc in &self.pointclouds {
+            for e57_point in self.reader.pointcloud_simple(pc)? {
+                let e57_point = e57_point?;
+                // scanners record misses (no return) as invalid points
+                let CartesianCoordinate::Valid { x, y, z } = e57_point.cartesian else {
+                    continue;
+                };
+                let mut point = new_point();
+                point.x = x;
+                point.y = y;
+                point.z = z;
+                if let Some(intensity) = e57_point.intensity {
+                    point.intensity = to_u16(intensity);
+                }
+                if let Some(color) = e57_point.color {
+                    point.color = Some(Color::new(
+                        to_u16(color.red),
+                        to_u16(color.green),
+                        to_u16(color.blue),
+                    ));
+                }
+                if !on_point(point)? {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+// one error type for everything the CLI can trip over,
+// so callers can tell a bad CRS from a truncated file from a full disk
+use arrow_schema::ArrowError;
+use geoarrow::error::GeoArrowError;
+use geozero::error::GeozeroError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum QuafferError {
+    // the CRS info in a file (or passed on the command line) couldn't be understood
+    #[error("CRS error: {0}")]
+    Crs(String),
+
+    // reading/writing the LAS/LAZ point data itself
+    #[cfg(feature = "laz_import")]
+    #[error("LAS error: {0}")]
+    Las(#[from] las::Error),
+
+    // reading E57 scans
+    #[cfg(feature = "laz_import")]
+    #[error("E57 error: {0}")]
+    E57(#[from] e57::Error),
+
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("GeoArrow error: {0}")]
+    GeoArrow(#[from] GeoArrowError),
+
+    // writing points out through geozero (FlatGeobuf)
+    #[error("Geozero error: {0}")]
+    Geozero(#[from] GeozeroError),
+
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    // a file that reads fine but isn't shaped the way we expect
+    // (missing columns, wrong types, bad option values...)
+    #[error("{0}")]
+    InvalidInput(String),
+}
+
+// gdal is only ever used for CRS wrangling
+#[cfg(feature = "gdal")]
+impl From<gdal::errors::GdalError> for QuafferError {
+    fn from(err: gdal::errors::GdalError) -> Self {
+        QuafferError::Crs(err.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, QuafferError>;
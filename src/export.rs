@@ -0,0 +1,52 @@
+// writing an imported geoparquet file out for tools that don't read it:
+// CSV, GeoJSON / GeoJSON text sequences, FlatGeobuf and PLY, a batch at a time
+use std::path::Path;
+
+use arrow_array::RecordBatchReader;
+
+use crate::error::Result;
+use crate::point_output::{OutputCrs, OutputFormat, carried_footer, open_sink, pick_format};
+use crate::read_parq::{PointFile, open_points};
+
+// `output` None writes to stdout, `format` None picks from the output's extension
+pub fn export(
+    filepath: &str,
+    output: Option<&Path>,
+    format: Option<OutputFormat>,
+    overwrite: bool,
+) -> Result<u64> {
+    let format = pick_format(format, output)?;
+    let PointFile {
+        reader,
+        geometry_column,
+        metadata,
+        crs,
+        ..
+    } = open_points(filepath, &[])?;
+    // stdout might be the output, so progress goes to stderr
+    let crs = match crs {
+        Some(crs) => Some(OutputCrs::from_projjson(&crs)?),
+        None => {
+            eprintln!("WARNING: {filepath} has no CRS, the {format} output won't have one either");
+            None
+        }
+    };
+    let mut sink = open_sink(
+        format,
+        output,
+        &reader.schema(),
+        &geometry_column,
+        crs.as_ref(),
+        carried_footer(&metadata),
+        overwrite,
+    )?;
+    let mut points_written: u64 = 0;
+    for batch in reader {
+        let batch = batch?;
+        points_written += batch.num_rows() as u64;
+        sink.write_batch(&batch)?;
+    }
+    sink.finish()?;
+    eprintln!("Wrote {points_written} points as {format}");
+    Ok(points_written)
+}
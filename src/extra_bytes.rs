@@ -0,0 +1,533 @@
+// decoding the custom per-point attributes described by the Extra Bytes VLR
+// (LAS 1.4 R15 section 2.5.2.4) into their own typed columns
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, Float32Array, Float64Array, Int8Array, Int16Array, Int32Array, Int64Array,
+    UInt8Array, UInt16Array, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field};
+use las::{Header, Vlr};
+
+use crate::error::{QuafferError, Result};
+
+pub const LAS_SPEC_USER_ID: &str = "LASF_Spec";
+pub const EXTRA_BYTES_RECORD_ID: u16 = 4;
+
+// every descriptor in the VLR is this many bytes
+const DESCRIPTOR_LEN: usize = 192;
+
+// bits of the descriptor's `options` field
+const NO_DATA_BIT: u8 = 0b00001;
+const SCALE_BIT: u8 = 0b01000;
+const OFFSET_BIT: u8 = 0b10000;
+
+// field metadata keys, on top of the descriptor's own `description`
+pub const LAS_DATA_TYPE_KEY: &str = "point_quaffer:las_data_type";
+pub const SCALE_KEY: &str = "point_quaffer:scale";
+pub const OFFSET_KEY: &str = "point_quaffer:offset";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtraBytesType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl ExtraBytesType {
+    fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => ExtraBytesType::U8,
+            2 => ExtraBytesType::I8,
+            3 => ExtraBytesType::U16,
+            4 => ExtraBytesType::I16,
+            5 => ExtraBytesType::U32,
+            6 => ExtraBytesType::I32,
+            7 => ExtraBytesType::U64,
+            8 => ExtraBytesType::I64,
+            9 => ExtraBytesType::F32,
+            10 => ExtraBytesType::F64,
+            _ => return None,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            ExtraBytesType::U8 | ExtraBytesType::I8 => 1,
+            ExtraBytesType::U16 | ExtraBytesType::I16 => 2,
+            ExtraBytesType::U32 | ExtraBytesType::I32 | ExtraBytesType::F32 => 4,
+            ExtraBytesType::U64 | ExtraBytesType::I64 | ExtraBytesType::F64 => 8,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ExtraBytesType::U8 => "u8",
+            ExtraBytesType::I8 => "i8",
+            ExtraBytesType::U16 => "u16",
+            ExtraBytesType::I16 => "i16",
+            ExtraBytesType::U32 => "u32",
+            ExtraBytesType::I32 => "i32",
+            ExtraBytesType::U64 => "u64",
+            ExtraBytesType::I64 => "i64",
+            ExtraBytesType::F32 => "f32",
+            ExtraBytesType::F64 => "f64",
+        }
+    }
+
+    fn arrow_type(&self) -> DataType {
+        match self {
+            ExtraBytesType::U8 => DataType::UInt8,
+            ExtraBytesType::I8 => DataType::Int8,
+            ExtraBytesType::U16 => DataType::UInt16,
+            ExtraBytesType::I16 => DataType::Int16,
+            ExtraBytesType::U32 => DataType::UInt32,
+            ExtraBytesType::I32 => DataType::Int32,
+            ExtraBytesType::U64 => DataType::UInt64,
+            ExtraBytesType::I64 => DataType::Int64,
+            ExtraBytesType::F32 => DataType::Float32,
+            ExtraBytesType::F64 => DataType::Float64,
+        }
+    }
+
+    // little endian value of this type from the start of `bytes`
+    fn read(&self, bytes: &[u8]) -> ExtraValue {
+        let mut buf = [0u8; 8];
+        buf[..self.size()].copy_from_slice(&bytes[..self.size()]);
+        match self {
+            ExtraBytesType::U8 => ExtraValue::UInt(buf[0] as u64),
+            ExtraBytesType::I8 => ExtraValue::Int(buf[0] as i8 as i64),
+            ExtraBytesType::U16 => ExtraValue::UInt(u16::from_le_bytes([buf[0], buf[1]]) as u64),
+            ExtraBytesType::I16 => ExtraValue::Int(i16::from_le_bytes([buf[0], buf[1]]) as i64),
+            ExtraBytesType::U32 => {
+                ExtraValue::UInt(u32::from_le_bytes(buf[..4].try_into().unwrap()) as u64)
+            }
+            ExtraBytesType::I32 => {
+                ExtraValue::Int(i32::from_le_bytes(buf[..4].try_into().unwrap()) as i64)
+            }
+            ExtraBytesType::U64 => ExtraValue::UInt(u64::from_le_bytes(buf)),
+            ExtraBytesType::I64 => ExtraValue::Int(i64::from_le_bytes(buf)),
+            ExtraBytesType::F32 => {
+                ExtraValue::Float(f32::from_le_bytes(buf[..4].try_into().unwrap()) as f64)
+            }
+            ExtraBytesType::F64 => ExtraValue::Float(f64::from_le_bytes(buf)),
+        }
+    }
+
+    // no_data/min/max are always stored 8 bytes wide, as u64, i64 or f64
+    fn read_wide(&self, bytes: &[u8; 8]) -> ExtraValue {
+        match self {
+            ExtraBytesType::U8
+            | ExtraBytesType::U16
+            | ExtraBytesType::U32
+            | ExtraBytesType::U64 => ExtraValue::UInt(u64::from_le_bytes(*bytes)),
+            ExtraBytesType::I8
+            | ExtraBytesType::I16
+            | ExtraBytesType::I32
+            | ExtraBytesType::I64 => ExtraValue::Int(i64::from_le_bytes(*bytes)),
+            ExtraBytesType::F32 | ExtraBytesType::F64 => {
+                ExtraValue::Float(f64::from_le_bytes(*bytes))
+            }
+        }
+    }
+}
+
+// a single decoded extra bytes value, before it goes into its typed column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtraValue {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+impl ExtraValue {
+    fn as_f64(&self) -> f64 {
+        match self {
+            ExtraValue::Int(value) => *value as f64,
+            ExtraValue::UInt(value) => *value as f64,
+            ExtraValue::Float(value) => *value,
+        }
+    }
+
+    fn as_i64(&self) -> i64 {
+        match self {
+            ExtraValue::Int(value) => *value,
+            ExtraValue::UInt(value) => *value as i64,
+            ExtraValue::Float(value) => *value as i64,
+        }
+    }
+
+    fn as_u64(&self) -> u64 {
+        match self {
+            ExtraValue::Int(value) => *value as u64,
+            ExtraValue::UInt(value) => *value,
+            ExtraValue::Float(value) => *value as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtraBytesDescriptor {
+    pub name: String,
+    pub description: String,
+    pub data_type: ExtraBytesType,
+    // where this attribute starts within a point's extra bytes
+    pub start: usize,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+    no_data: Option<ExtraValue>,
+}
+
+// fixed width, nul padded strings
+fn read_name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// every attribute becomes its own column, so blank names get one from their position
+// in the VLR and repeated names get a `_2`, `_3`... suffix
+fn unique_name(taken: &[ExtraBytesDescriptor], name: String, index: usize) -> String {
+    let name = if name.is_empty() {
+        format!("extra_{index}")
+    } else {
+        name
+    };
+    let is_taken = |candidate: &str| taken.iter().any(|descriptor| descriptor.name == candidate);
+    if !is_taken(&name) {
+        return name;
+    }
+    let renamed = (2..)
+        .map(|n| format!("{name}_{n}"))
+        .find(|candidate| !is_taken(candidate))
+        .expect("there's always a free suffix");
+    eprintln!(
+        "WARNING: there's more than one extra bytes attribute `{name}`, calling this one `{renamed}`"
+    );
+    renamed
+}
+
+fn read_triple(bytes: &[u8]) -> [u8; 8] {
+    // only the first of the three slots is used for non-array types
+    bytes[..8].try_into().unwrap()
+}
+
+impl ExtraBytesDescriptor {
+    // parse the Extra Bytes VLR (or EVLR), returns an empty list if there isn't one
+    pub fn from_header(header: &Header) -> Result<Vec<ExtraBytesDescriptor>> {
+        let vlr: Option<&Vlr> =
+            header.vlrs().iter().chain(header.evlrs()).find(|vlr| {
+                vlr.user_id == LAS_SPEC_USER_ID && vlr.record_id == EXTRA_BYTES_RECORD_ID
+            });
+        let Some(vlr) = vlr else {
+            return Ok(Vec::new());
+        };
+        if !vlr.data.len().is_multiple_of(DESCRIPTOR_LEN) {
+            return Err(QuafferError::InvalidInput(format!(
+                "Extra Bytes VLR is {} bytes, not a multiple of {DESCRIPTOR_LEN}",
+                vlr.data.len()
+            )));
+        }
+
+        let mut descriptors = Vec::new();
+        let mut start = 0;
+        for (index, raw) in vlr.data.chunks_exact(DESCRIPTOR_LEN).enumerate() {
+            // layout: reserved[2], data_type, options, name[32], unused[4],
+            // no_data[24], min[24], max[24], scale[24], offset[24], description[32]
+            let (type_code, options) = (raw[2], raw[3]);
+            let name = read_name(&raw[4..36]);
+            let description = read_name(&raw[160..192]);
+            let Some(data_type) = ExtraBytesType::from_code(type_code) else {
+                let size = match type_code {
+                    // undocumented bytes, `options` holds how many there are
+                    0 => options as usize,
+                    // the deprecated 2 and 3 element array types
+                    11..=30 => {
+                        let base = ExtraBytesType::from_code((type_code - 1) % 10 + 1).unwrap();
+                        base.size() * if type_code <= 20 { 2 } else { 3 }
+                    }
+                    other => {
+                        return Err(QuafferError::InvalidInput(format!(
+                            "extra bytes attribute `{name}` has unknown data type {other}"
+                        )));
+                    }
+                };
+                eprintln!(
+                    "WARNING: skipping extra bytes attribute `{name}` (data type {type_code})"
+                );
+                start += size;
+                continue;
+            };
+
+            let no_data = (options & NO_DATA_BIT != 0)
+                .then(|| data_type.read_wide(&read_triple(&raw[40..64])));
+            let scale =
+                (options & SCALE_BIT != 0).then(|| f64::from_le_bytes(read_triple(&raw[112..136])));
+            let offset = (options & OFFSET_BIT != 0)
+                .then(|| f64::from_le_bytes(read_triple(&raw[136..160])));
+            descriptors.push(ExtraBytesDescriptor {
+                name: unique_name(&descriptors, name, index),
+                description,
+                data_type,
+                start,
+                scale,
+                offset,
+                no_data,
+            });
+            start += data_type.size();
+        }
+        Ok(descriptors)
+    }
+
+    // scaled/offset values come out as f64, everything else keeps its own type
+    pub fn arrow_type(&self) -> DataType {
+        if self.scale.is_some() || self.offset.is_some() {
+            DataType::Float64
+        } else {
+            self.data_type.arrow_type()
+        }
+    }
+
+    // nullable, both for no_data values and for merged tiles without this attribute
+    pub fn field(&self, column_name: &str) -> Field {
+        let mut metadata = HashMap::from([
+            ("description".to_string(), self.description.clone()),
+            (
+                LAS_DATA_TYPE_KEY.to_string(),
+                self.data_type.name().to_string(),
+            ),
+        ]);
+        if let Some(scale) = self.scale {
+            metadata.insert(SCALE_KEY.to_string(), scale.to_string());
+        }
+        if let Some(offset) = self.offset {
+            metadata.insert(OFFSET_KEY.to_string(), offset.to_string());
+        }
+        Field::new(column_name, self.arrow_type(), true).with_metadata(metadata)
+    }
+
+    // pull this attribute out of a point's extra bytes, None for no_data
+    // (or a point that's too short to hold it)
+    pub fn decode(&self, extra_bytes: &[u8]) -> Option<ExtraValue> {
+        let bytes = extra_bytes.get(self.start..self.start + self.data_type.size())?;
+        let raw = self.data_type.read(bytes);
+        if self.no_data == Some(raw) {
+            return None;
+        }
+        if self.scale.is_none() && self.offset.is_none() {
+            return Some(raw);
+        }
+        Some(ExtraValue::Float(
+            raw.as_f64() * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0),
+        ))
+    }
+
+    // turn a column's worth of decoded values into an arrow array of `arrow_type`
+    pub fn to_array(&self, values: impl Iterator<Item = Option<ExtraValue>>) -> ArrayRef {
+        match self.arrow_type() {
+            DataType::UInt8 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_u64() as u8))
+                    .collect::<UInt8Array>(),
+            ),
+            DataType::Int8 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_i64() as i8))
+                    .collect::<Int8Array>(),
+            ),
+            DataType::UInt16 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_u64() as u16))
+                    .collect::<UInt16Array>(),
+            ),
+            DataType::Int16 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_i64() as i16))
+                    .collect::<Int16Array>(),
+            ),
+            DataType::UInt32 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_u64() as u32))
+                    .collect::<UInt32Array>(),
+            ),
+            DataType::Int32 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_i64() as i32))
+                    .collect::<Int32Array>(),
+            ),
+            DataType::UInt64 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_u64()))
+                    .collect::<UInt64Array>(),
+            ),
+            DataType::Int64 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_i64()))
+                    .collect::<Int64Array>(),
+            ),
+            DataType::Float32 => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_f64() as f32))
+                    .collect::<Float32Array>(),
+            ),
+            _ => Arc::new(
+                values
+                    .map(|v| v.map(|v| v.as_f64()))
+                    .collect::<Float64Array>(),
+            ),
+        }
+    }
+}
+
+// add `other`'s attributes to `merged` (by name) for a merged output,
+// the same name with a different type can't share a column
+pub fn merge_descriptors(
+    merged: &mut Vec<ExtraBytesDescriptor>,
+    other: &[ExtraBytesDescriptor],
+) -> Result<()> {
+    for descriptor in other {
+        match merged
+            .iter()
+            .find(|existing| existing.name == descriptor.name)
+        {
+            Some(existing) if existing.arrow_type() != descriptor.arrow_type() => {
+                return Err(QuafferError::InvalidInput(format!(
+                    "extra bytes attribute `{}` is {} in one tile and {} in another",
+                    descriptor.name,
+                    existing.arrow_type(),
+                    descriptor.arrow_type()
+                )));
+            }
+            Some(_) => {}
+            None => merged.push(descriptor.clone()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::Builder;
+
+    // a single 192 byte descriptor, scale/offset/no_data only set when given
+    fn descriptor_bytes(
+        type_code: u8,
+        name: &str,
+        no_data: Option<[u8; 8]>,
+        scale: Option<f64>,
+        offset: Option<f64>,
+    ) -> Vec<u8> {
+        let mut raw = vec![0u8; DESCRIPTOR_LEN];
+        raw[2] = type_code;
+        raw[4..4 + name.len()].copy_from_slice(name.as_bytes());
+        if let Some(no_data) = no_data {
+            raw[3] |= NO_DATA_BIT;
+            raw[40..48].copy_from_slice(&no_data);
+        }
+        if let Some(scale) = scale {
+            raw[3] |= SCALE_BIT;
+            raw[112..120].copy_from_slice(&scale.to_le_bytes());
+        }
+        if let Some(offset) = offset {
+            raw[3] |= OFFSET_BIT;
+            raw[136..144].copy_from_slice(&offset.to_le_bytes());
+        }
+        raw[160..170].copy_from_slice(b"test field");
+        raw
+    }
+
+    fn header_with(data: Vec<u8>) -> Header {
+        let mut builder = Builder::default();
+        builder.vlrs.push(Vlr {
+            user_id: LAS_SPEC_USER_ID.to_string(),
+            record_id: EXTRA_BYTES_RECORD_ID,
+            description: String::new(),
+            data,
+        });
+        builder.into_header().unwrap()
+    }
+
+    #[test]
+    fn descriptors_are_decoded_with_their_offsets() {
+        let mut data = descriptor_bytes(3, "range", None, None, None);
+        // undocumented bytes just move the next attribute along
+        let mut undocumented = descriptor_bytes(0, "padding", None, None, None);
+        undocumented[3] = 3;
+        data.extend(undocumented);
+        data.extend(descriptor_bytes(4, "height", None, Some(0.01), Some(100.0)));
+        let descriptors = ExtraBytesDescriptor::from_header(&header_with(data)).unwrap();
+
+        assert_eq!(descriptors.len(), 2);
+        assert_eq!(descriptors[0].name, "range");
+        assert_eq!(descriptors[0].description, "test field");
+        assert_eq!(descriptors[0].data_type, ExtraBytesType::U16);
+        assert_eq!(descriptors[0].start, 0);
+        assert_eq!(descriptors[0].arrow_type(), DataType::UInt16);
+        assert_eq!(descriptors[1].name, "height");
+        assert_eq!(descriptors[1].data_type, ExtraBytesType::I16);
+        assert_eq!(descriptors[1].start, 5);
+        assert_eq!(descriptors[1].scale, Some(0.01));
+        assert_eq!(descriptors[1].offset, Some(100.0));
+        assert_eq!(descriptors[1].arrow_type(), DataType::Float64);
+    }
+
+    #[test]
+    fn values_are_scaled_offset_and_masked() {
+        let data = [
+            descriptor_bytes(4, "height", None, Some(0.5), Some(10.0)),
+            descriptor_bytes(6, "id", Some((-1i64).to_le_bytes()), None, None),
+        ]
+        .concat();
+        let descriptors = ExtraBytesDescriptor::from_header(&header_with(data)).unwrap();
+        let (height, id) = (&descriptors[0], &descriptors[1]);
+
+        let point = [(-4i16).to_le_bytes().as_slice(), &7i32.to_le_bytes()].concat();
+        assert_eq!(height.decode(&point), Some(ExtraValue::Float(8.0)));
+        assert_eq!(id.decode(&point), Some(ExtraValue::Int(7)));
+
+        let no_data = [0i16.to_le_bytes().as_slice(), &(-1i32).to_le_bytes()].concat();
+        assert_eq!(id.decode(&no_data), None);
+        // too short to hold the attribute
+        assert_eq!(id.decode(&point[..4]), None);
+    }
+
+    #[test]
+    fn blank_and_repeated_names_are_made_unique() {
+        let data = [
+            descriptor_bytes(1, "", None, None, None),
+            descriptor_bytes(1, "amplitude", None, None, None),
+            descriptor_bytes(1, "amplitude", None, None, None),
+            descriptor_bytes(1, "amplitude_2", None, None, None),
+        ]
+        .concat();
+        let names: Vec<String> = ExtraBytesDescriptor::from_header(&header_with(data))
+            .unwrap()
+            .into_iter()
+            .map(|descriptor| descriptor.name)
+            .collect();
+        assert_eq!(
+            names,
+            ["extra_0", "amplitude", "amplitude_2", "amplitude_2_2"]
+        );
+    }
+
+    #[test]
+    fn bad_vlrs_are_rejected() {
+        assert!(ExtraBytesDescriptor::from_header(&header_with(vec![0; 100])).is_err());
+        let unknown_type = descriptor_bytes(31, "mystery", None, None, None);
+        assert!(ExtraBytesDescriptor::from_header(&header_with(unknown_type)).is_err());
+        assert!(
+            ExtraBytesDescriptor::from_header(&Builder::default().into_header().unwrap())
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
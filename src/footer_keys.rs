@@ -0,0 +1,11 @@
+// names laz-import writes into geoparquet files that the readers (read, query, export,
+// info) look for again, kept in one place so both sides always agree
+// (module-specific keys like the clip or query records live next to their code)
+
+// GeoParquet 1.1 bbox covering column, for points it's just x/y twice
+// but it's what readers look at to skip row groups
+pub const BBOX_COLUMN: &str = "bbox";
+// every footer entry laz-import adds on top of the geoparquet ones starts with this
+pub const FOOTER_KEY_PREFIX: &str = "point_quaffer:";
+// the source LAS headers, a JSON list with one entry per source tile
+pub const LAS_HEADER_METADATA_KEY: &str = "point_quaffer:las_headers";
@@ -0,0 +1,296 @@
+// decoding for the 'GeoTIFF' flavor of LAS CRS info
+// (the GeoKeyDirectoryTag/GeoDoubleParams/GeoAsciiParams VLRs that older
+// LAS 1.2/1.3 files use instead of a WKT VLR).
+// only CRSs given as EPSG codes are supported, user defined ones (ellipsoid,
+// projection parameters etc. spelled out in GeoDoubleParams) are not turned into WKT
+// spec: http://docs.opengeospatial.org/is/19-008r4/19-008r4.html
+use las::Vlr;
+
+use crate::error::{QuafferError, Result};
+
+// all the CRS VLRs live under this user id
+pub const PROJECTION_USER_ID: &str = "LASF_Projection";
+pub const GEO_KEY_DIRECTORY_RECORD_ID: u16 = 34735;
+pub const GEO_DOUBLE_PARAMS_RECORD_ID: u16 = 34736;
+pub const GEO_ASCII_PARAMS_RECORD_ID: u16 = 34737;
+pub const WKT_RECORD_ID: u16 = 2112;
+
+// geokey ids we care about
+const GT_MODEL_TYPE_KEY: u16 = 1024;
+const GT_CITATION_KEY: u16 = 1026;
+const GEOGRAPHIC_TYPE_KEY: u16 = 2048;
+const GEOG_CITATION_KEY: u16 = 2049;
+const PROJECTED_CS_TYPE_KEY: u16 = 3072;
+const PCS_CITATION_KEY: u16 = 3073;
+const VERTICAL_CS_TYPE_KEY: u16 = 4096;
+
+// code used by the spec for 'user defined, go read the other keys'
+const USER_DEFINED: u16 = 32767;
+
+// where a key's value actually lives, per the TIFFTagLocation field
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoKeyValue {
+    Short(u16),
+    Doubles(Vec<f64>),
+    Ascii(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct GeoKeyEntry {
+    pub key_id: u16,
+    pub value: GeoKeyValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeoKeyDirectory {
+    pub entries: Vec<GeoKeyEntry>,
+}
+
+// the VLR payloads are little endian u16s/f64s
+fn read_u16s(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+fn read_f64s(data: &[u8]) -> Vec<f64> {
+    data.chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+        .collect()
+}
+
+fn find_vlr(vlrs: &[&Vlr], record_id: u16) -> Option<Vec<u8>> {
+    vlrs.iter()
+        .find(|vlr| vlr.user_id == PROJECTION_USER_ID && vlr.record_id == record_id)
+        .map(|vlr| vlr.data.clone())
+}
+
+impl GeoKeyDirectory {
+    // pull the geokey directory (and the params it points into) out of a set of VLRs,
+    // returns Ok(None) if there isn't a GeoKeyDirectoryTag VLR at all
+    pub fn from_vlrs(vlrs: &[&Vlr]) -> Result<Option<GeoKeyDirectory>> {
+        let Some(directory_data) = find_vlr(vlrs, GEO_KEY_DIRECTORY_RECORD_ID) else {
+            return Ok(None);
+        };
+        let doubles = find_vlr(vlrs, GEO_DOUBLE_PARAMS_RECORD_ID)
+            .map(|data| read_f64s(&data))
+            .unwrap_or_default();
+        let ascii = find_vlr(vlrs, GEO_ASCII_PARAMS_RECORD_ID)
+            .map(|data| String::from_utf8_lossy(&data).to_string())
+            .unwrap_or_default();
+
+        let shorts = read_u16s(&directory_data);
+        // header is KeyDirectoryVersion, KeyRevision, MinorRevision, NumberOfKeys
+        if shorts.len() < 4 {
+            return Err(QuafferError::Crs(
+                "GeoKeyDirectoryTag VLR is too short to hold its header".to_string(),
+            ));
+        }
+        let number_of_keys = shorts[3] as usize;
+        let key_shorts = &shorts[4..];
+        if key_shorts.len() < number_of_keys * 4 {
+            return Err(QuafferError::Crs(format!(
+                "GeoKeyDirectoryTag VLR says it has {number_of_keys} keys but only holds {}",
+                key_shorts.len() / 4
+            )));
+        }
+
+        let mut entries = Vec::with_capacity(number_of_keys);
+        for key in key_shorts.chunks_exact(4).take(number_of_keys) {
+            let (key_id, location, count, value_offset) =
+                (key[0], key[1], key[2] as usize, key[3] as usize);
+            let value = match location {
+                // value is stored right in the entry
+                0 => GeoKeyValue::Short(value_offset as u16),
+                GEO_DOUBLE_PARAMS_RECORD_ID => GeoKeyValue::Doubles(
+                    doubles
+                        .get(value_offset..value_offset + count)
+                        .ok_or_else(|| {
+                            QuafferError::Crs(format!(
+                                "geokey {key_id} points past GeoDoubleParams"
+                            ))
+                        })?
+                        .to_vec(),
+                ),
+                GEO_ASCII_PARAMS_RECORD_ID => GeoKeyValue::Ascii(
+                    ascii
+                        .get(value_offset..value_offset + count)
+                        .ok_or_else(|| {
+                            QuafferError::Crs(format!("geokey {key_id} points past GeoAsciiParams"))
+                        })?
+                        // strings are '|' terminated
+                        .trim_end_matches(['|', '\0'])
+                        .to_string(),
+                ),
+                // GeoTIFF allows pointing at other tiff tags, but there are none in a LAS file
+                other => {
+                    eprintln!(
+                        "WARNING: skipping geokey {key_id} stored in unsupported location {other}"
+                    );
+                    continue;
+                }
+            };
+            entries.push(GeoKeyEntry { key_id, value });
+        }
+        Ok(Some(GeoKeyDirectory { entries }))
+    }
+
+    fn short(&self, key_id: u16) -> Option<u16> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key_id == key_id)
+            .and_then(|entry| match entry.value {
+                GeoKeyValue::Short(value) => Some(value),
+                _ => None,
+            })
+    }
+
+    fn ascii(&self, key_id: u16) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key_id == key_id)
+            .and_then(|entry| match &entry.value {
+                GeoKeyValue::Ascii(value) => Some(value.as_str()),
+                _ => None,
+            })
+    }
+
+    // EPSG code for the horizontal CRS, projected if the model is projected
+    // (or unspecified), geographic otherwise
+    pub fn horizontal_epsg(&self) -> Option<u16> {
+        let valid = |code: &u16| *code != 0 && *code != USER_DEFINED;
+        let projected = self.short(PROJECTED_CS_TYPE_KEY).filter(valid);
+        let geographic = self.short(GEOGRAPHIC_TYPE_KEY).filter(valid);
+        match self.short(GT_MODEL_TYPE_KEY) {
+            // ModelTypeGeographic
+            Some(2) => geographic,
+            _ => projected.or(geographic),
+        }
+    }
+
+    // EPSG code for the vertical datum, if one's given
+    pub fn vertical_epsg(&self) -> Option<u16> {
+        self.short(VERTICAL_CS_TYPE_KEY)
+            .filter(|code| *code != 0 && *code != USER_DEFINED)
+    }
+
+    // true if the horizontal CRS is spelled out key by key instead of by EPSG code,
+    // which we can't build a CRS from
+    pub fn is_user_defined(&self) -> bool {
+        self.horizontal_epsg().is_none()
+            && [PROJECTED_CS_TYPE_KEY, GEOGRAPHIC_TYPE_KEY]
+                .iter()
+                .any(|key_id| self.short(*key_id) == Some(USER_DEFINED))
+    }
+
+    // any human readable description of the CRS, useful when there's no EPSG code
+    pub fn citation(&self) -> Option<&str> {
+        self.ascii(PCS_CITATION_KEY)
+            .or(self.ascii(GT_CITATION_KEY))
+            .or(self.ascii(GEOG_CITATION_KEY))
+    }
+
+    // a definition GDAL's SpatialRef can parse, e.g. "EPSG:26915" or "EPSG:6339+5703"
+    pub fn crs_definition(&self) -> Option<String> {
+        let horizontal = self.horizontal_epsg()?;
+        Some(match self.vertical_epsg() {
+            Some(vertical) => format!("EPSG:{horizontal}+{vertical}"),
+            None => format!("EPSG:{horizontal}"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vlr(record_id: u16, data: Vec<u8>) -> Vlr {
+        Vlr {
+            user_id: PROJECTION_USER_ID.to_string(),
+            record_id,
+            description: String::new(),
+            data,
+        }
+    }
+
+    // header plus (key id, location, count, value) entries
+    fn directory(keys: &[[u16; 4]]) -> Vlr {
+        let mut shorts = vec![1, 1, 0, keys.len() as u16];
+        shorts.extend(keys.iter().flatten());
+        vlr(
+            GEO_KEY_DIRECTORY_RECORD_ID,
+            shorts
+                .iter()
+                .flat_map(|short| short.to_le_bytes())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn projected_and_vertical_codes_are_read() {
+        let ascii = vlr(GEO_ASCII_PARAMS_RECORD_ID, b"NAD83 / UTM 15N|".to_vec());
+        let doubles = vlr(
+            GEO_DOUBLE_PARAMS_RECORD_ID,
+            [1.5f64, 2.5].iter().flat_map(|d| d.to_le_bytes()).collect(),
+        );
+        let keys = directory(&[
+            [GT_MODEL_TYPE_KEY, 0, 1, 1],
+            [PCS_CITATION_KEY, GEO_ASCII_PARAMS_RECORD_ID, 16, 0],
+            [PROJECTED_CS_TYPE_KEY, 0, 1, 26915],
+            [VERTICAL_CS_TYPE_KEY, 0, 1, 5703],
+            // some parameter stored as doubles
+            [3080, GEO_DOUBLE_PARAMS_RECORD_ID, 2, 0],
+        ]);
+        let geokeys = GeoKeyDirectory::from_vlrs(&[&ascii, &keys, &doubles])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(geokeys.entries.len(), 5);
+        assert_eq!(
+            geokeys.entries[4].value,
+            GeoKeyValue::Doubles(vec![1.5, 2.5])
+        );
+        assert_eq!(geokeys.horizontal_epsg(), Some(26915));
+        assert_eq!(geokeys.vertical_epsg(), Some(5703));
+        assert_eq!(geokeys.citation(), Some("NAD83 / UTM 15N"));
+        assert_eq!(geokeys.crs_definition().as_deref(), Some("EPSG:26915+5703"));
+        assert!(!geokeys.is_user_defined());
+    }
+
+    #[test]
+    fn geographic_models_use_the_geographic_code() {
+        let keys = directory(&[
+            [GT_MODEL_TYPE_KEY, 0, 1, 2],
+            [GEOGRAPHIC_TYPE_KEY, 0, 1, 4326],
+            [PROJECTED_CS_TYPE_KEY, 0, 1, 26915],
+        ]);
+        let geokeys = GeoKeyDirectory::from_vlrs(&[&keys]).unwrap().unwrap();
+        assert_eq!(geokeys.crs_definition().as_deref(), Some("EPSG:4326"));
+    }
+
+    #[test]
+    fn user_defined_crs_has_no_definition() {
+        let keys = directory(&[
+            [GT_MODEL_TYPE_KEY, 0, 1, 1],
+            [PROJECTED_CS_TYPE_KEY, 0, 1, USER_DEFINED],
+        ]);
+        let geokeys = GeoKeyDirectory::from_vlrs(&[&keys]).unwrap().unwrap();
+        assert_eq!(geokeys.horizontal_epsg(), None);
+        assert_eq!(geokeys.crs_definition(), None);
+        assert!(geokeys.is_user_defined());
+    }
+
+    #[test]
+    fn broken_directories_are_rejected() {
+        assert!(GeoKeyDirectory::from_vlrs(&[]).unwrap().is_none());
+        let short = vlr(GEO_KEY_DIRECTORY_RECORD_ID, vec![1, 0, 1, 0]);
+        assert!(GeoKeyDirectory::from_vlrs(&[&short]).is_err());
+        // claims two keys, holds one
+        let mut truncated = directory(&[[GT_MODEL_TYPE_KEY, 0, 1, 1]]);
+        truncated.data[6] = 2;
+        assert!(GeoKeyDirectory::from_vlrs(&[&truncated]).is_err());
+        // points past the end of GeoDoubleParams (which isn't there at all)
+        let dangling = directory(&[[3080, GEO_DOUBLE_PARAMS_RECORD_ID, 1, 0]]);
+        assert!(GeoKeyDirectory::from_vlrs(&[&dangling]).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+// writing a geoparquet file from laz-import back out as LAS/LAZ,
+// for handing cleaned up points to tools that only speak LAS (PDAL, CloudCompare...)
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::UInt8Type;
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatchReader, StringArray, UInt8Array};
+use arrow_schema::Schema;
+use gdal::spatial_ref::SpatialRef;
+use geoparquet::reader::{GeoParquetReaderBuilder, GeoParquetRecordBatchReader};
+use las::point::{Classification, Format, ScanDirection, Waveform};
+use las::{Builder, Color, Point, Transform, Vector, Vlr, Writer};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::metadata::ParquetMetaData;
+use parquet::file::statistics::Statistics;
+
+use crate::error::{QuafferError, Result};
+use crate::extra_bytes::LAS_DATA_TYPE_KEY;
+use crate::geotiff_keys::{PROJECTION_USER_ID, WKT_RECORD_ID};
+use crate::laz_to_gpq::{PartialFile, decode_class_codes};
+use crate::read_parq::{Coords, Numbers};
+
+const BATCH_ROW_SIZE: usize = 65536;
+// millimetres for projected CRSs, about a centimetre at the equator for degrees
+const PROJECTED_SCALE: f64 = 0.001;
+const GEOGRAPHIC_SCALE: f64 = 0.0000001;
+// offsets get rounded to this, so they're tidy numbers in the middle of the data
+const OFFSET_STEP: f64 = 1000.0;
+
+// every numeric column we know how to put back into a LAS point
+const NUMBER_COLUMNS: [&str; 20] = [
+    "z",
+    "intensity",
+    "return_number",
+    "number_of_returns",
+    "scan_angle",
+    "point_source_id",
+    "user_data",
+    "gps_time",
+    "scanner_channel",
+    "red",
+    "green",
+    "blue",
+    "nir",
+    "wave_packet_descriptor_index",
+    "waveform_byte_offset",
+    "waveform_packet_size",
+    "waveform_return_location",
+    "waveform_x_t",
+    "waveform_y_t",
+    "waveform_z_t",
+];
+const FLAG_COLUMNS: [&str; 5] = [
+    "is_synthetic",
+    "is_key_point",
+    "is_withheld",
+    "is_overlap",
+    "is_edge_of_flight_line",
+];
+
+// the classification column, as class codes decoded from the dictionary labels
+pub enum Classes<'a> {
+    Codes(UInt8Array),
+    // --legacy-schema imports wrote the `Classification` debug names
+    Names(&'a StringArray, &'a HashMap<String, u8>),
+}
+
+impl<'a> Classes<'a> {
+    // None if the column is neither shape
+    pub fn from_column(
+        column: &'a ArrayRef,
+        class_codes: &'a HashMap<String, u8>,
+    ) -> Result<Option<Self>> {
+        Ok(match column.as_dictionary_opt::<UInt8Type>() {
+            Some(dictionary) => Some(Classes::Codes(decode_class_codes(dictionary)?)),
+            None => column
+                .as_string_opt::<i32>()
+                .map(|names| Classes::Names(names, class_codes)),
+        })
+    }
+
+    pub fn is_valid(&self, idx: usize) -> bool {
+        match self {
+            Classes::Codes(codes) => codes.is_valid(idx),
+            Classes::Names(names, _) => names.is_valid(idx),
+        }
+    }
+
+    pub fn code(&self, idx: usize) -> u8 {
+        match self {
+            Classes::Codes(codes) => codes.value(idx),
+            Classes::Names(names, codes) => codes.get(names.value(idx)).copied().unwrap_or(1),
+        }
+    }
+}
+
+// the scan direction column, true = left to right, or the legacy debug names
+enum ScanDirections<'a> {
+    Flags(&'a BooleanArray),
+    Names(&'a StringArray),
+}
+
+impl ScanDirections<'_> {
+    fn direction(&self, idx: usize) -> ScanDirection {
+        let left_to_right = match self {
+            ScanDirections::Flags(flags) => flags.value(idx),
+            ScanDirections::Names(names) => names.value(idx) == "LeftToRight",
+        };
+        if left_to_right {
+            ScanDirection::LeftToRight
+        } else {
+            ScanDirection::RightToLeft
+        }
+    }
+}
+
+// legacy class name -> code, by asking las what it would have called each code
+pub fn legacy_class_codes() -> HashMap<String, u8> {
+    (0..=u8::MAX)
+        .filter_map(|code| {
+            Classification::new(code)
+                .ok()
+                .map(|class| (format!("{class:?}"), code))
+        })
+        .collect()
+}
+
+// the smallest point format that can hold every column the file has
+fn pick_point_format(schema: &Schema) -> Result<Format> {
+    let has = |name: &str| schema.column_with_name(name).is_some();
+    let gps_time = has("gps_time");
+    let color = has("red") && has("green") && has("blue");
+    let nir = has("nir");
+    let waveform = has("wave_packet_descriptor_index");
+    // channels and NIR only exist in the 1.4 formats
+    let extended = has("scanner_channel") || nir;
+    let number = if extended {
+        match (color || nir, waveform) {
+            (false, false) => 6,
+            (true, false) if nir => 8,
+            (true, false) => 7,
+            (false, true) => 9,
+            (true, true) => 10,
+        }
+    } else {
+        match (gps_time, color, waveform) {
+            (false, false, false) => 0,
+            (true, false, false) => 1,
+            (false, true, false) => 2,
+            (true, true, false) => 3,
+            (_, false, true) => 4,
+            (_, true, true) => 5,
+        }
+    };
+    Ok(Format::new(number)?)
+}
+
+// the geoparquet PROJJSON as WKT, plus whether it's in degrees
+pub fn crs_wkt(projjson: &str) -> Result<(String, bool)> {
+    let srs = SpatialRef::from_definition(projjson)?;
+    Ok((srs.to_wkt()?, srs.is_geographic()))
+}
+
+// min/max of every row group's stats for a double column, None if any are missing
+fn column_range(metadata: &ParquetMetaData, path: &str) -> Option<(f64, f64)> {
+    let schema = metadata.file_metadata().schema_descr();
+    let column =
+        (0..schema.num_columns()).find(|&col| schema.column(col).path().string() == path)?;
+    let mut range: Option<(f64, f64)> = None;
+    for row_group in metadata.row_groups() {
+        let Statistics::Double(stats) = row_group.column(column).statistics()? else {
+            return None;
+        };
+        let (lo, hi) = (*stats.min_opt()?, *stats.max_opt()?);
+        range = Some(range.map_or((lo, hi), |(min, max)| (min.min(lo), max.max(hi))));
+    }
+    range
+}
+
+// x/y/z extent of the whole file without reading any points: the coordinate column stats
+// for separated points (or the loose `z` column), else the `geo` metadata bbox.
+// None for axes neither one covers (e.g. z of interleaved or WKB points with a 2D bbox)
+fn file_extent(
+    metadata: &ParquetMetaData,
+    geometry_column: &str,
+    bbox: Option<&[f64]>,
+) -> [Option<(f64, f64)>; 3] {
+    // [xmin, ymin, xmax, ymax] or [xmin, ymin, zmin, xmax, ymax, zmax]
+    let bbox_positions = match bbox.map(<[f64]>::len) {
+        Some(6) => [Some((0, 3)), Some((1, 4)), Some((2, 5))],
+        Some(4) => [Some((0, 2)), Some((1, 3)), None],
+        _ => [None, None, None],
+    };
+    let mut extent = [None; 3];
+    for (axis, name) in ["x", "y", "z"].into_iter().enumerate() {
+        extent[axis] = column_range(metadata, &format!("{geometry_column}.{name}"))
+            .or_else(|| column_range(metadata, name))
+            .or_else(|| {
+                let (lo, hi) = bbox_positions[axis]?;
+                let bbox = bbox?;
+                Some((bbox[lo], bbox[hi]))
+            });
+    }
+    extent
+}
+
+// the smallest power of ten scale, no finer than `scale`, that keeps every known coordinate
+// within i32 of its offset. an explicit --scale that can't do that is an error instead
+fn fit_scale(
+    extent: &[Option<(f64, f64)>; 3],
+    offset: &[Option<f64>; 3],
+    scale: f64,
+    explicit: bool,
+) -> Result<f64> {
+    let reach = extent
+        .iter()
+        .zip(offset)
+        .filter_map(|(range, offset)| {
+            let ((min, max), offset) = (range.as_ref()?, offset.as_ref()?);
+            Some((max - offset).abs().max((min - offset).abs()))
+        })
+        .fold(0.0, f64::max);
+    let limit = f64::from(i32::MAX);
+    if reach / scale <= limit {
+        return Ok(scale);
+    }
+    if explicit {
+        return Err(QuafferError::InvalidInput(format!(
+            "points span up to {reach} from the offset, which doesn't fit in LAS \
+            coordinates at scale {scale}, pass a coarser --scale"
+        )));
+    }
+    let fitted = 10f64.powf((reach / limit).log10().ceil());
+    eprintln!("WARNING: points span too far for scale {scale}, using {fitted} instead");
+    Ok(fitted)
+}
+
+fn open_writer(
+    path: &Path,
+    mut format: Format,
+    compress: bool,
+    scale: f64,
+    offset: [f64; 3],
+    wkt: Option<&str>,
+) -> Result<Writer<BufWriter<File>>> {
+    format.is_compressed = compress;
+    let mut builder = Builder::from((1, 4));
+    builder.point_format = format;
+    builder.generating_software = "point-quaffer".to_string();
+    builder.transforms = Vector {
+        x: Transform {
+            scale,
+            offset: offset[0],
+        },
+        y: Transform {
+            scale,
+            offset: offset[1],
+        },
+        z: Transform {
+            scale,
+            offset: offset[2],
+        },
+    };
+    if let Some(wkt) = wkt {
+        // null terminated, like everyone else writes it
+        let mut data = wkt.as_bytes().to_vec();
+        data.push(0);
+        builder.vlrs.push(Vlr {
+            user_id: PROJECTION_USER_ID.to_string(),
+            record_id: WKT_RECORD_ID,
+            description: "OGC WKT".to_string(),
+            data,
+        });
+        builder.has_wkt_crs = true;
+    }
+    let header = builder.into_header()?;
+    Ok(Writer::new(BufWriter::new(File::create(path)?), header)?)
+}
+
+// write a geoparquet file (from laz-import) out as LAS, or LAZ if `outfile_path` ends in .laz
+// `scale` overrides the coordinate resolution, otherwise it's picked from the CRS units
+pub fn gpq_to_laz(
+    filepath: &str,
+    outfile_path: &str,
+    scale: Option<f64>,
+    overwrite: bool,
+) -> Result<u64> {
+    if !overwrite && Path::new(outfile_path).exists() {
+        return Err(QuafferError::InvalidInput(format!(
+            "{outfile_path} already exists, pass --force to overwrite it"
+        )));
+    }
+    if scale.is_some_and(|scale| scale <= 0.0) {
+        return Err(QuafferError::InvalidInput(
+            "scale must be greater than zero".to_string(),
+        ));
+    }
+    let file = File::open(filepath)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let geoparquet_metadata = builder.geoparquet_metadata().ok_or_else(|| {
+        QuafferError::InvalidInput(format!("{filepath} doesn't have any geoparquet metadata"))
+    })??;
+    let geometry_column = geoparquet_metadata.primary_column.clone();
+    let crs = geoparquet_metadata
+        .columns
+        .get(&geometry_column)
+        .and_then(|column| column.crs.as_ref())
+        .filter(|crs| !crs.is_null())
+        .map(|crs| crs_wkt(&crs.to_string()))
+        .transpose()?;
+    if crs.is_none() {
+        eprintln!("WARNING: {filepath} has no CRS, the LAS file won't have one either");
+    }
+    let bbox = geoparquet_metadata
+        .columns
+        .get(&geometry_column)
+        .and_then(|column| column.bbox.clone());
+    let extent = file_extent(builder.metadata(), &geometry_column, bbox.as_deref());
+    let geoarrow_schema =
+        builder.geoarrow_schema(&geoparquet_metadata, true, Default::default())?;
+    let parquet_reader = builder.with_batch_size(BATCH_ROW_SIZE).build()?;
+    let reader = GeoParquetRecordBatchReader::try_new(parquet_reader, geoarrow_schema)?;
+    let schema = reader.schema();
+
+    let format = pick_point_format(&schema)?;
+    println!("Writing point format {}", format.to_u8()?);
+    let skipped: Vec<&str> = schema
+        .fields()
+        .iter()
+        .filter(|field| field.metadata().contains_key(LAS_DATA_TYPE_KEY))
+        .map(|field| field.name().as_str())
+        .collect();
+    if !skipped.is_empty() {
+        eprintln!(
+            "WARNING: extra bytes columns aren't written back to LAS yet, skipping {}",
+            skipped.join(", ")
+        );
+    }
+    // offsets in the middle of the data, so coordinates can reach either way from them
+    // (axes the metadata doesn't cover get theirs from the first point)
+    let mut offset = extent.map(|range| {
+        range.map(|(min, max)| ((min + max) / 2.0 / OFFSET_STEP).round() * OFFSET_STEP)
+    });
+    let default_scale = match crs {
+        Some((_, true)) => GEOGRAPHIC_SCALE,
+        _ => PROJECTED_SCALE,
+    };
+    let scale = fit_scale(
+        &extent,
+        &offset,
+        scale.unwrap_or(default_scale),
+        scale.is_some(),
+    )?;
+    let wkt = crs.as_ref().map(|(wkt, _)| wkt.as_str());
+    let compress = Path::new(outfile_path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("laz"));
+    let partial_file = PartialFile::new(Path::new(outfile_path));
+    let class_codes = legacy_class_codes();
+
+    // the writer waits for the first point, for any offsets the extent didn't give us
+    let mut writer = None;
+    let mut points_written: u64 = 0;
+    for batch in reader {
+        let batch = batch?;
+        let coords = Coords::from_batch(&batch, &geometry_column)?;
+        let mut numbers = HashMap::new();
+        for name in NUMBER_COLUMNS {
+            if let Some(column) = Numbers::from_batch(&batch, name)? {
+                numbers.insert(name, column);
+            }
+        }
+        let int = |name: &str, idx: usize| numbers.get(name).and_then(|column| column.int(idx));
+        let float = |name: &str, idx: usize| numbers.get(name).and_then(|column| column.float(idx));
+        let flags: HashMap<&str, &BooleanArray> = FLAG_COLUMNS
+            .iter()
+            .filter_map(|name| {
+                let column = batch.column_by_name(name)?.as_boolean_opt()?;
+                Some((*name, column))
+            })
+            .collect();
+        let flag = |name: &str, idx: usize| flags.get(name).is_some_and(|flags| flags.value(idx));
+        let classes = batch
+            .column_by_name("classification")
+            .map(|column| Classes::from_column(column, &class_codes))
+            .transpose()?
+            .flatten();
+        let scan_directions = batch.column_by_name("scan_direction").and_then(|column| {
+            column
+                .as_boolean_opt()
+                .map(ScanDirections::Flags)
+                .or_else(|| column.as_string_opt::<i32>().map(ScanDirections::Names))
+        });
+
+        for idx in 0..batch.num_rows() {
+            let (x, y, z) = coords.xyz(idx);
+            let mut is_overlap = flag("is_overlap", idx);
+            let code = classes.as_ref().map_or(1, |classes| classes.code(idx));
+            // 12 is reserved for overlap in 1.4, las wants it in the overlap flag instead
+            let classification = Classification::new(code).unwrap_or_else(|_| {
+                is_overlap = true;
+                Classification::Unclassified
+            });
+            let color = format.has_color.then(|| Color {
+                red: int("red", idx).unwrap_or_default() as u16,
+                green: int("green", idx).unwrap_or_default() as u16,
+                blue: int("blue", idx).unwrap_or_default() as u16,
+            });
+            let waveform = format.has_waveform.then(|| Waveform {
+                wave_packet_descriptor_index: int("wave_packet_descriptor_index", idx)
+                    .unwrap_or_default() as u8,
+                byte_offset_to_waveform_data: int("waveform_byte_offset", idx).unwrap_or_default(),
+                waveform_packet_size_in_bytes: int("waveform_packet_size", idx).unwrap_or_default()
+                    as u32,
+                return_point_waveform_location: float("waveform_return_location", idx)
+                    .unwrap_or_default() as f32,
+                x_t: float("waveform_x_t", idx).unwrap_or_default() as f32,
+                y_t: float("waveform_y_t", idx).unwrap_or_default() as f32,
+                z_t: float("waveform_z_t", idx).unwrap_or_default() as f32,
+            });
+            let point = Point {
+                x,
+                y,
+                z: z.or_else(|| float("z", idx)).unwrap_or_default(),
+                intensity: int("intensity", idx).unwrap_or_default() as u16,
+                return_number: int("return_number", idx).unwrap_or_default() as u8,
+                number_of_returns: int("number_of_returns", idx).unwrap_or_default() as u8,
+                scan_direction: scan_directions
+                    .as_ref()
+                    .map_or(ScanDirection::RightToLeft, |directions| {
+                        directions.direction(idx)
+                    }),
+                is_edge_of_flight_line: flag("is_edge_of_flight_line", idx),
+                classification,
+                is_synthetic: flag("is_synthetic", idx),
+                is_key_point: flag("is_key_point", idx),
+                is_withheld: flag("is_withheld", idx),
+                is_overlap,
+                scanner_channel: int("scanner_channel", idx).unwrap_or_default() as u8,
+                scan_angle: float("scan_angle", idx).unwrap_or_default() as f32,
+                user_data: int("user_data", idx).unwrap_or_default() as u8,
+                point_source_id: int("point_source_id", idx).unwrap_or_default() as u16,
+                gps_time: format
+                    .has_gps_time
+                    .then(|| float("gps_time", idx).unwrap_or_default()),
+                color,
+                waveform,
+                nir: format
+                    .has_nir
+                    .then(|| int("nir", idx).unwrap_or_default() as u16),
+                ..Default::default()
+            };
+            if writer.is_none() {
+                for (axis, coord) in offset.iter_mut().zip([x, y, point.z]) {
+                    if axis.is_none() {
+                        *axis = Some((coord / OFFSET_STEP).round() * OFFSET_STEP);
+                    }
+                }
+                writer = Some(open_writer(
+                    &partial_file.path,
+                    format,
+                    compress,
+                    scale,
+                    offset.map(Option::unwrap_or_default),
+                    wkt,
+                )?);
+            }
+            if let Some(writer) = &mut writer {
+                writer.write_point(point)?;
+                points_written += 1;
+            }
+        }
+        println!("{points_written} points so far");
+    }
+    let mut writer = match writer {
+        Some(writer) => writer,
+        None => open_writer(
+            &partial_file.path,
+            format,
+            compress,
+            scale,
+            offset.map(Option::unwrap_or_default),
+            wkt,
+        )?,
+    };
+    // fills in the header bounds and point counts
+    writer.close()?;
+    drop(writer);
+    partial_file.persist()?;
+    println!("Done! Wrote {points_written} points to {outfile_path}");
+    Ok(points_written)
+}
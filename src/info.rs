@@ -0,0 +1,289 @@
+// a quick look at a LAS/LAZ or GeoParquet file from its headers/footer alone,
+// no point data gets decoded
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use las::Reader;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::metadata::ParquetMetaData;
+use parquet::file::statistics::Statistics;
+use serde_json::{Map, Value, json};
+
+use crate::error::{QuafferError, Result};
+use crate::las_header::header_json;
+use crate::laz_to_gpq::crs_from_header;
+use crate::pruning::is_unsigned;
+use crate::read_parq::row_group_bounds;
+
+// LAS 1.4 has 15 return slots, older versions 5
+const MAX_RETURNS: u8 = 15;
+
+fn las_info(path: &Path) -> Result<Value> {
+    let reader = Reader::from_path(path)?;
+    let header = reader.header();
+    let mut info = header_json(&path.to_string_lossy(), header, false);
+    let points_by_return: Vec<u64> = (1..=MAX_RETURNS)
+        .map_while(|n| header.number_of_points_by_return(n))
+        .collect();
+    info["points_by_return"] = json!(points_by_return);
+    info["crs"] = json!(crs_from_header(header)?.map(|crs| crs.label()));
+    Ok(info)
+}
+
+// a statistics min/max as JSON, None for types there's no sensible way to show
+// (unsigned columns are stored as signed ints, `unsigned` reads them back as such)
+fn stat_range(stats: &Statistics, unsigned: bool) -> Option<(Value, Value)> {
+    Some(match stats {
+        Statistics::Boolean(stats) => (json!(stats.min_opt()?), json!(stats.max_opt()?)),
+        Statistics::Int32(stats) if unsigned => (
+            json!(*stats.min_opt()? as u32),
+            json!(*stats.max_opt()? as u32),
+        ),
+        Statistics::Int32(stats) => (json!(stats.min_opt()?), json!(stats.max_opt()?)),
+        Statistics::Int64(stats) if unsigned => (
+            json!(*stats.min_opt()? as u64),
+            json!(*stats.max_opt()? as u64),
+        ),
+        Statistics::Int64(stats) => (json!(stats.min_opt()?), json!(stats.max_opt()?)),
+        Statistics::Float(stats) => (json!(stats.min_opt()?), json!(stats.max_opt()?)),
+        Statistics::Double(stats) => (json!(stats.min_opt()?), json!(stats.max_opt()?)),
+        Statistics::ByteArray(stats) => (
+            json!(String::from_utf8_lossy(stats.min_opt()?.data())),
+            json!(String::from_utf8_lossy(stats.max_opt()?.data())),
+        ),
+        Statistics::Int96(_) | Statistics::FixedLenByteArray(_) => return None,
+    })
+}
+
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .unwrap_or_default()
+            .total_cmp(&b.as_f64().unwrap_or_default()),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+// per leaf column, everything the footer knows, folded over all the row groups
+fn column_stats(metadata: &ParquetMetaData) -> Vec<Value> {
+    let schema = metadata.file_metadata().schema_descr();
+    (0..schema.num_columns())
+        .map(|col| {
+            let mut min: Option<Value> = None;
+            let mut max: Option<Value> = None;
+            let mut null_count: Option<u64> = Some(0);
+            let (mut compressed, mut uncompressed) = (0, 0);
+            let mut compression = None;
+            let mut encodings = Vec::new();
+            let unsigned = is_unsigned(&schema.column(col));
+            for row_group in metadata.row_groups() {
+                let column = row_group.column(col);
+                compressed += column.compressed_size();
+                uncompressed += column.uncompressed_size();
+                compression.get_or_insert_with(|| column.compression().to_string());
+                for encoding in column.encodings() {
+                    let encoding = encoding.to_string();
+                    if !encodings.contains(&encoding) {
+                        encodings.push(encoding);
+                    }
+                }
+                let stats = column.statistics();
+                // a row group without null counts means we can't say for the file
+                null_count = null_count
+                    .zip(stats.and_then(|stats| stats.null_count_opt()))
+                    .map(|(total, count)| total + count);
+                if let Some((row_group_min, row_group_max)) =
+                    stats.and_then(|stats| stat_range(stats, unsigned))
+                {
+                    if min
+                        .as_ref()
+                        .is_none_or(|min| compare(&row_group_min, min).is_lt())
+                    {
+                        min = Some(row_group_min);
+                    }
+                    if max
+                        .as_ref()
+                        .is_none_or(|max| compare(&row_group_max, max).is_gt())
+                    {
+                        max = Some(row_group_max);
+                    }
+                }
+            }
+            json!({
+                "column": schema.column(col).path().string(),
+                "physical_type": schema.column(col).physical_type().to_string(),
+                "min": min,
+                "max": max,
+                "null_count": null_count,
+                "compression": compression,
+                "encodings": encodings,
+                "compressed_bytes": compressed,
+                "uncompressed_bytes": uncompressed,
+            })
+        })
+        .collect()
+}
+
+// a short name for a PROJJSON CRS, like "NAD83 / UTM zone 15N (EPSG:26915)"
+fn crs_label(crs: &Value) -> Value {
+    if crs.is_null() {
+        return Value::Null;
+    }
+    let name = crs["name"].as_str().unwrap_or("unnamed CRS");
+    match (crs["id"]["authority"].as_str(), &crs["id"]["code"]) {
+        (Some(authority), code) if !code.is_null() => {
+            let code = code
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| code.to_string());
+            json!(format!("{name} ({authority}:{code})"))
+        }
+        _ => json!(name),
+    }
+}
+
+fn parquet_info(path: &Path) -> Result<Value> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let metadata = builder.metadata();
+    let schema: Vec<Value> = builder
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| {
+            json!({
+                "name": field.name(),
+                "type": field.data_type().to_string(),
+                "nullable": field.is_nullable(),
+            })
+        })
+        .collect();
+    let row_groups: Vec<Value> = metadata
+        .row_groups()
+        .iter()
+        .map(|row_group| {
+            json!({
+                "rows": row_group.num_rows(),
+                "bytes": row_group.compressed_size(),
+                "bbox": row_group_bounds(row_group)
+                    .map(|bbox| [bbox.minx, bbox.miny, bbox.maxx, bbox.maxy]),
+            })
+        })
+        .collect();
+    // every footer entry that's JSON (geo, our point_quaffer: ones...), decoded
+    let mut footer = Map::new();
+    for key_value in metadata
+        .file_metadata()
+        .key_value_metadata()
+        .into_iter()
+        .flatten()
+    {
+        // the arrow schema is already shown above
+        if key_value.key == "ARROW:schema" {
+            continue;
+        }
+        let value = key_value.value.as_deref().unwrap_or_default();
+        let value = serde_json::from_str(value).unwrap_or_else(|_| json!(value));
+        footer.insert(key_value.key.clone(), value);
+    }
+    let geo = footer.get("geo").cloned().unwrap_or(Value::Null);
+    let crs = geo["columns"]
+        .as_object()
+        .map(|columns| {
+            columns
+                .iter()
+                .map(|(column, column_meta)| (column.clone(), crs_label(&column_meta["crs"])))
+                .collect::<Map<String, Value>>()
+        })
+        .unwrap_or_default();
+    Ok(json!({
+        "source": path.to_string_lossy(),
+        "rows": metadata.file_metadata().num_rows(),
+        "created_by": metadata.file_metadata().created_by(),
+        "schema": schema,
+        "row_groups": row_groups,
+        "columns": column_stats(metadata),
+        "crs": crs,
+        "metadata": footer,
+    }))
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Object(_) | Value::Array(_))
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// indented `key: value` text, lists of plain values stay on one line
+fn print_text(value: &Value, indent: usize) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                match value {
+                    Value::Array(items) if items.iter().all(is_scalar) => {
+                        let items: Vec<String> = items.iter().map(scalar_text).collect();
+                        println!("{pad}{key}: [{}]", items.join(", "));
+                    }
+                    value if is_scalar(value) => println!("{pad}{key}: {}", scalar_text(value)),
+                    value => {
+                        println!("{pad}{key}:");
+                        print_text(value, indent + 1);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if is_scalar(item) {
+                    println!("{pad}- {}", scalar_text(item));
+                } else {
+                    println!("{pad}[{i}]");
+                    print_text(item, indent + 1);
+                }
+            }
+        }
+        scalar => println!("{pad}{}", scalar_text(scalar)),
+    }
+}
+
+// LAS files start with "LASF", parquet files with "PAR1"
+fn is_las(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 4];
+    File::open(path)?.read_exact(&mut magic)?;
+    match &magic {
+        b"LASF" => Ok(true),
+        b"PAR1" => Ok(false),
+        _ => Err(QuafferError::InvalidInput(format!(
+            "{} isn't a LAS/LAZ or parquet file",
+            path.display()
+        ))),
+    }
+}
+
+pub fn info(path: &Path, as_json: bool) -> Result<()> {
+    let info = if is_las(path)? {
+        las_info(path)?
+    } else {
+        parquet_info(path)?
+    };
+    if as_json {
+        let text = serde_json::to_string_pretty(&info).map_err(|err| {
+            QuafferError::InvalidInput(format!("couldn't write info as JSON: {err}"))
+        })?;
+        println!("{text}");
+    } else {
+        print_text(&info, 0);
+    }
+    Ok(())
+}
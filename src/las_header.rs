@@ -0,0 +1,52 @@
+// the source LAS header + VLRs, kept in the parquet footer as provenance
+// so any output can be traced back to the surveyor's original tiles
+// (under footer_keys::LAS_HEADER_METADATA_KEY)
+use base64::engine::{Engine, general_purpose::STANDARD};
+use las::{Header, Vlr};
+use serde_json::{Value, json};
+
+fn vlr_json(vlr: &Vlr, with_data: bool) -> Value {
+    let mut entry = json!({
+        "user_id": vlr.user_id,
+        "record_id": vlr.record_id,
+        "description": vlr.description,
+        "length": vlr.data.len(),
+    });
+    // EVLRs can be whole waveform blobs, those only get their length recorded
+    if with_data {
+        entry["data_base64"] = Value::from(STANDARD.encode(&vlr.data));
+    }
+    entry
+}
+
+// `vlr_data` includes the (base64) VLR payloads, not just their sizes
+pub fn header_json(source: &str, header: &Header, vlr_data: bool) -> Value {
+    let version = header.version();
+    let format = header.point_format();
+    let transforms = header.transforms();
+    let bounds = header.bounds();
+    json!({
+        "source": source,
+        "version": format!("{}.{}", version.major, version.minor),
+        "system_identifier": header.system_identifier(),
+        "generating_software": header.generating_software(),
+        "creation_date": header.date().map(|date| date.to_string()),
+        "file_source_id": header.file_source_id(),
+        "guid": header.guid().to_string(),
+        "global_encoding": {
+            "gps_time_type": format!("{:?}", header.gps_time_type()),
+            "has_synthetic_return_numbers": header.has_synthetic_return_numbers(),
+            "has_wkt_crs": header.has_wkt_crs(),
+        },
+        "point_format": format.to_u8().ok(),
+        "point_count": header.number_of_points(),
+        "scale": [transforms.x.scale, transforms.y.scale, transforms.z.scale],
+        "offset": [transforms.x.offset, transforms.y.offset, transforms.z.offset],
+        "bounds": {
+            "min": [bounds.min.x, bounds.min.y, bounds.min.z],
+            "max": [bounds.max.x, bounds.max.y, bounds.max.z],
+        },
+        "vlrs": header.vlrs().iter().map(|vlr| vlr_json(vlr, vlr_data)).collect::<Vec<_>>(),
+        "evlrs": header.evlrs().iter().map(|vlr| vlr_json(vlr, false)).collect::<Vec<_>>(),
+    })
+}
@@ -1,44 +1,92 @@
+use geo_traits::{CoordTrait, Dimensions};
 use geoarrow::error::{GeoArrowError, GeoArrowResult};
 use geoarrow_schema::crs::CrsTransform;
 // point cloud readin' son
-use las::point::Classification;
-use las::{LazParallelism, Reader, ReaderOptions};
+use las::point::{Format, Waveform};
+use las::{Color, Header, Point, Vlr};
 use serde_json::Value;
 use std::collections::HashMap;
 // opening/closing files
 use std::fs::File;
-use std::io::BufReader;
+use std::path::{Path, PathBuf};
 // geoarrow!
-use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
-use arrow_schema::{DataType, Field, Schema};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, UInt8Type, UInt16Type, UInt32Type};
+use arrow_array::{
+    ArrayRef, ArrowPrimitiveType, BooleanArray, DictionaryArray, Float32Array, Float64Array,
+    Int64Array, PrimitiveArray, RecordBatch, StringArray, StructArray, UInt8Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
 use geoarrow::array::PointBuilder;
-use geoarrow::datatypes::{Crs, Dimension, PointType};
+use geoarrow::datatypes::{CoordType, Crs, Dimension, PointType};
 use geoarrow_array::GeoArrowArray;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 // geoparquet writer
-use geoparquet::writer::{GeoParquetRecordBatchEncoder, GeoParquetWriterOptionsBuilder};
+use geoparquet::writer::{
+    GeoParquetRecordBatchEncoder, GeoParquetWriterEncoding, GeoParquetWriterOptionsBuilder,
+};
 use parquet::arrow::ArrowWriter;
-use parquet::basic::Compression;
-use parquet::file::properties::WriterProperties;
+use parquet::file::metadata::KeyValue;
 
-use gdal::spatial_ref::SpatialRef;
+use gdal::spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+use crate::bbox::Bbox;
+use crate::clip::{CLIP_METADATA_KEY, ClipRegion, PreparedClip};
+use crate::error::{QuafferError, Result};
+use crate::extra_bytes::{ExtraBytesDescriptor, ExtraValue};
+use crate::footer_keys::{BBOX_COLUMN, LAS_HEADER_METADATA_KEY};
+use crate::geotiff_keys::{GeoKeyDirectory, PROJECTION_USER_ID, WKT_RECORD_ID};
+use crate::parquet_options::{GeometryEncoding, ParquetOptions};
+use crate::point_filter::{PointFilter, class_label, label_class_code};
+use crate::point_source::{PointSource, SourceOptions, open_source};
+use crate::sampling::{Sampler, Sampling};
+use crate::spatial_sort::{SpatialSort, SpatialSorter};
 
-#[derive(Debug)]
-struct WKTStringTransform {
+#[derive(Debug, Clone)]
+pub struct WKTStringTransform {
     wkt_str: String,
-    proj_str: String,
+    projjson: Value,
 }
 
 impl WKTStringTransform {
-    pub fn new(wkt_str: String) -> Self {
-        let cleaned_wkt = wkt_str.trim_ascii().trim_end_matches('\0').to_string();
-        let spatial_ref = SpatialRef::from_wkt(&cleaned_wkt).expect("ruh ruh gdal broke");
-        let proj_str = spatial_ref
-            .to_projjson()
-            .expect("oops couldn't make a projjson str");
-        WKTStringTransform { wkt_str, proj_str }
+    // parse the WKT up front so a bad CRS shows up here instead of mid-write
+    pub fn try_new(wkt_str: String) -> Result<Self> {
+        let wkt_str = wkt_str.trim_ascii().trim_end_matches('\0').to_string();
+        let spatial_ref = SpatialRef::from_wkt(&wkt_str)?;
+        let proj_str = spatial_ref.to_projjson()?;
+        let projjson = serde_json::from_str(&proj_str).map_err(|err| {
+            QuafferError::Crs(format!("gdal made a PROJJSON string we can't parse: {err}"))
+        })?;
+        Ok(WKTStringTransform { wkt_str, projjson })
+    }
+
+    pub fn from_spatial_ref(spatial_ref: &SpatialRef) -> Result<Self> {
+        WKTStringTransform::try_new(spatial_ref.to_wkt()?)
+    }
+
+    pub fn spatial_ref(&self) -> Result<SpatialRef> {
+        Ok(SpatialRef::from_wkt(&self.wkt_str)?)
+    }
+
+    // short name for summaries, e.g. "EPSG:6339"
+    pub fn label(&self) -> String {
+        let authority = self
+            .spatial_ref()
+            .ok()
+            .and_then(|srs| Some((srs.auth_name().ok()?, srs.auth_code().ok()?)));
+        match authority {
+            Some((name, code)) => format!("{name}:{code}"),
+            None => "custom".to_string(),
+        }
+    }
+
+    // two CRSes are treated as the same if gdal says so
+    pub fn is_same(&self, other: &WKTStringTransform) -> bool {
+        match (self.spatial_ref(), other.spatial_ref()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => self.wkt_str == other.wkt_str,
+        }
     }
 }
 impl CrsTransform for WKTStringTransform {
@@ -46,9 +94,7 @@ impl CrsTransform for WKTStringTransform {
         &self,
         _crs: &Crs,
     ) -> std::result::Result<Option<serde_json::Value>, GeoArrowError> {
-        let json_value: Value =
-            serde_json::from_str(&self.proj_str).expect("couldn't parse json str for proj");
-        Ok(Some(json_value))
+        Ok(Some(self.projjson.clone()))
     }
 
     fn _convert_to_wkt(&self, _crs: &Crs) -> GeoArrowResult<Option<String>> {
@@ -56,9 +102,7 @@ impl CrsTransform for WKTStringTransform {
     }
 
     fn extract_projjson(&self, _crs: &Crs) -> geoarrow::error::GeoArrowResult<Option<Value>> {
-        let json_value: Value =
-            serde_json::from_str(&self.proj_str).expect("couldn't parse json str for proj");
-        Ok(Some(json_value))
+        Ok(Some(self.projjson.clone()))
     }
 
     fn extract_wkt(&self, _crs: &Crs) -> geoarrow::error::GeoArrowResult<Option<String>> {
@@ -66,183 +110,1384 @@ impl CrsTransform for WKTStringTransform {
     }
 }
 
-// open up a point cloud .laz file
-// (testing with USGS data)
-// and dump it into a geoparquet file
-pub fn read_laz_to_gpq(
-    filename: String,
-    filter_to_ground: bool,
-    max_points: Option<i64>,
-    outfile_path: String,
-) -> Result<()> {
-    println!("Opening point cloud .laz file at {filename}");
-    let file = File::open(filename).unwrap();
-    let options = ReaderOptions::default();
-    options.with_laz_parallelism(LazParallelism::Yes);
-    let mut reader = Reader::with_options(BufReader::new(file), options)?;
-    let mut wkt_transform = None;
-    let header = reader.header();
-    // try to grab the wkt string verison of the CRS
-    // (according to LAZ spec CRS can either be WKT or 'GeoTIFF' based -
-    // so far all the USGS data sampled has has WKT)
-    if header.has_wkt_crs() {
-        let header_vlrs = header.vlrs();
-        for vlr in header_vlrs {
-            if vlr.description.contains("WKT") {
-                println!("found a WKT header!");
-                let data = vlr.data.clone();
-                let parsed_wkt_string = String::from_utf8(data).unwrap();
-                wkt_transform = Some(WKTStringTransform::new(parsed_wkt_string));
+// figure out the CRS of a .laz file
+// (according to LAZ spec CRS can either be WKT or 'GeoTIFF' based -
+// most USGS data has WKT, older LAS 1.2/1.3 surveys tend to have geokeys)
+// what it found goes to stderr, so `info --json` output stays clean
+pub fn crs_from_header(header: &Header) -> Result<Option<WKTStringTransform>> {
+    let vlrs: Vec<&Vlr> = header.vlrs().iter().chain(header.evlrs()).collect();
+    let wkt_vlr = vlrs.iter().copied().find(|vlr| {
+        (vlr.user_id == PROJECTION_USER_ID && vlr.record_id == WKT_RECORD_ID)
+            || vlr.description.contains("WKT")
+    });
+    let from_wkt_vlr = |vlr: &Vlr| -> Result<WKTStringTransform> {
+        eprintln!("found a WKT header!");
+        let parsed_wkt_string = String::from_utf8(vlr.data.clone())
+            .map_err(|err| QuafferError::Crs(format!("WKT VLR isn't valid UTF-8: {err}")))?;
+        WKTStringTransform::try_new(parsed_wkt_string)
+    };
+
+    // the global encoding WKT bit says the WKT VLR is the one to trust
+    if header.has_wkt_crs()
+        && let Some(vlr) = wkt_vlr
+    {
+        return Ok(Some(from_wkt_vlr(vlr)?));
+    }
+
+    if let Some(geokeys) = GeoKeyDirectory::from_vlrs(&vlrs)? {
+        match (geokeys.horizontal_epsg(), geokeys.crs_definition()) {
+            (Some(horizontal), Some(definition)) => {
+                eprintln!("found GeoTIFF geokeys for {definition}");
+                // not every gdal/proj combo knows every compound horizontal+vertical pair,
+                // so fall back to just the horizontal CRS
+                let spatial_ref = SpatialRef::from_definition(&definition).or_else(|err| {
+                    eprintln!("couldn't build {definition} ({err}), dropping the vertical datum");
+                    SpatialRef::from_epsg(horizontal as u32)
+                })?;
+                return Ok(Some(WKTStringTransform::from_spatial_ref(&spatial_ref)?));
             }
+            _ if geokeys.is_user_defined() => eprintln!(
+                "WARNING: user defined GeoTIFF CRSs aren't supported, ignoring it \
+                 (citation: {}), pass --source-crs to set the CRS",
+                geokeys.citation().unwrap_or("none")
+            ),
+            _ => eprintln!(
+                "WARNING: GeoTIFF geokeys don't reference an EPSG code (citation: {})",
+                geokeys.citation().unwrap_or("none")
+            ),
         }
     }
-    // Vectors to accumulate point data
-    let mut x_coords: Vec<f64> = Vec::new();
-    let mut y_coords: Vec<f64> = Vec::new();
-    let mut z_coords: Vec<f64> = Vec::new();
-    let mut fids: Vec<i64> = Vec::new();
-    let mut intensities: Vec<i64> = Vec::new();
-    let mut return_numbers: Vec<i64> = Vec::new();
-    let mut number_of_returns: Vec<i64> = Vec::new();
-    let mut scan_directions: Vec<String> = Vec::new();
-    let mut classifications: Vec<String> = Vec::new();
-    let mut scan_angles: Vec<f64> = Vec::new();
-    let mut point_source_ids: Vec<i64> = Vec::new();
-    let mut gps_times: Vec<Option<f64>> = Vec::new();
-
-    let has_max = max_points.is_some();
-    let mut i: i64 = 0;
-    for point in reader.points() {
-        if has_max && i > max_points.unwrap() {
-            println!("Hit limit for max number of points, continuing...");
-            break;
-        }
-        let pnt = point?;
-        // if filter flag was provided, and point isn't ground, dip early
-        if filter_to_ground && pnt.classification != Classification::Ground {
-            continue;
-        }
-        x_coords.push(pnt.x);
-        y_coords.push(pnt.y);
-        z_coords.push(pnt.z);
-        fids.push(i);
-        intensities.push(pnt.intensity as i64);
-        return_numbers.push(pnt.return_number as i64);
-        number_of_returns.push(pnt.number_of_returns as i64);
-        scan_directions.push(match pnt.scan_direction {
-            las::point::ScanDirection::LeftToRight => "LeftToRight".to_string(),
-            las::point::ScanDirection::RightToLeft => "RightToLeft".to_string(),
-        });
-        classifications.push(format!("{:?}", pnt.classification));
-        scan_angles.push(pnt.scan_angle as f64);
-        point_source_ids.push(pnt.point_source_id as i64);
-        gps_times.push(pnt.gps_time);
 
-        i += 1;
+    // some writers include a WKT VLR but forget to set the global encoding bit
+    match wkt_vlr {
+        Some(vlr) => Ok(Some(from_wkt_vlr(vlr)?)),
+        None => Ok(None),
+    }
+}
+
+// default number of points buffered before a RecordBatch is built and written,
+// each batch also becomes its own parquet row group
+// (matches the parquet crate's default max row group size)
+pub const DEFAULT_BATCH_SIZE: usize = 1024 * 1024;
+
+// name of the geometry column for true 3D points
+pub const XYZ_COLUMN: &str = "xyz";
+// name of the geometry column for the older XY point + separate `z` column layout
+pub const XY_COLUMN: &str = "xy";
+
+// knobs for how a .laz file gets turned into geoparquet
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    // which points to keep, by classification and flags
+    pub filter: PointFilter,
+    // only keep points inside this bbox/polygon
+    pub clip: Option<ClipRegion>,
+    // stop once this many points have been kept, if provided
+    pub max_points: Option<i64>,
+    // thin out the points that get past the filter and clip
+    pub sampling: Sampling,
+    // seed for `sampling`, same seed + same file = same points
+    pub seed: u64,
+    // points per RecordBatch (and parquet row group)
+    pub batch_size: usize,
+    // write XYZ point geometries, otherwise XY points plus a loose `z` column
+    pub xyz: bool,
+    // how the point geometries are laid out in the file
+    pub geometry_encoding: GeometryEncoding,
+    // error out instead of warning when the file's CRS can't be determined
+    pub require_crs: bool,
+    // reproject points into this CRS (anything gdal's SpatialRef::from_definition takes)
+    pub target_crs: Option<String>,
+    // replace output files that already exist instead of refusing to
+    pub overwrite: bool,
+    // write the old all-Int64/Float64/string columns instead of LAS-typed ones
+    pub legacy_schema: bool,
+    // compression/encoding/statistics for the parquet file
+    pub parquet: ParquetOptions,
+    // write a GeoParquet 1.1 `bbox` covering column so readers can skip row groups
+    pub bbox_column: bool,
+    // reorder each tile's points along a space-filling curve before writing
+    pub sort: SpatialSort,
+    // input format, CRS and column mapping for non-LAS point clouds
+    pub source: SourceOptions,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            filter: PointFilter::default(),
+            clip: None,
+            max_points: None,
+            sampling: Sampling::All,
+            seed: 0,
+            batch_size: DEFAULT_BATCH_SIZE,
+            xyz: true,
+            geometry_encoding: GeometryEncoding::default(),
+            require_crs: false,
+            target_crs: None,
+            overwrite: false,
+            legacy_schema: false,
+            parquet: ParquetOptions::default(),
+            bbox_column: true,
+            sort: SpatialSort::None,
+            source: SourceOptions::default(),
+        }
+    }
+}
+
+// bare-bones 3D coord so the geoarrow point builder can take z values
+struct XyzCoord {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl CoordTrait for XyzCoord {
+    type T = f64;
+
+    fn dim(&self) -> Dimensions {
+        Dimensions::Xyz
+    }
+
+    fn x(&self) -> f64 {
+        self.x
+    }
+
+    fn y(&self) -> f64 {
+        self.y
+    }
+
+    fn nth_or_panic(&self, n: usize) -> f64 {
+        match n {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("XyzCoord only has 3 dimensions"),
+        }
+    }
+}
+
+// which of the optional LAS attributes end up as columns, from the point format
+// (so e.g. a format 1 file doesn't get empty color columns)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PointAttributes {
+    pub gps_time: bool,
+    // red/green/blue, formats 2/3/5/7/8/10
+    pub color: bool,
+    // near infrared, formats 8/10
+    pub nir: bool,
+    // waveform packet fields, formats 4/5/9/10
+    pub waveform: bool,
+    // LAS 1.4 formats 6-10
+    pub scanner_channel: bool,
+}
+
+impl PointAttributes {
+    pub fn from_format(format: &Format) -> Self {
+        PointAttributes {
+            gps_time: format.has_gps_time,
+            color: format.has_color,
+            nir: format.has_nir,
+            waveform: format.has_waveform,
+            scanner_channel: format.is_extended,
+        }
+    }
+
+    // every column either side has, for merging tiles with different point formats
+    // (points from tiles without an attribute get nulls)
+    pub fn union(self, other: PointAttributes) -> Self {
+        PointAttributes {
+            gps_time: self.gps_time || other.gps_time,
+            color: self.color || other.color,
+            nir: self.nir || other.nir,
+            waveform: self.waveform || other.waveform,
+            scanner_channel: self.scanner_channel || other.scanner_channel,
+        }
+    }
+}
+
+// an integer column as its LAS spec type, or widened to Int64 for the legacy schema
+fn int_column<T>(values: impl Iterator<Item = Option<T::Native>>, legacy: bool) -> ArrayRef
+where
+    T: ArrowPrimitiveType,
+    T::Native: Into<i64>,
+{
+    if legacy {
+        Arc::new(
+            values
+                .map(|value| value.map(Into::into))
+                .collect::<Int64Array>(),
+        )
+    } else {
+        Arc::new(values.collect::<PrimitiveArray<T>>())
+    }
+}
+
+// same idea for LAS's f32 fields
+fn float_column(values: impl Iterator<Item = Option<f32>>, legacy: bool) -> ArrayRef {
+    if legacy {
+        Arc::new(
+            values
+                .map(|value| value.map(f64::from))
+                .collect::<Float64Array>(),
+        )
+    } else {
+        Arc::new(values.collect::<Float32Array>())
+    }
+}
+
+// the schema side of `int_column`/`float_column`
+fn las_type(spec_type: DataType, legacy: bool) -> DataType {
+    match (spec_type, legacy) {
+        (DataType::Float32, true) => DataType::Float64,
+        (_, true) => DataType::Int64,
+        (spec_type, false) => spec_type,
+    }
+}
+
+// label for every possible class code, the values of the classification dictionary
+// (a key is the LAS class code while writing, but parquet only keeps the labels,
+// so read back keys are positions in whatever dictionary the file ended up with)
+fn classification_labels() -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(
+        (0..=u8::MAX).map(class_label),
+    ))
+}
+
+// class codes of a classification column read back from a file, through its labels
+pub fn decode_class_codes(dictionary: &DictionaryArray<UInt8Type>) -> Result<UInt8Array> {
+    let labels = dictionary.values().as_string_opt::<i32>().ok_or_else(|| {
+        QuafferError::InvalidInput(format!(
+            "classification labels are {}, expected strings",
+            dictionary.values().data_type()
+        ))
+    })?;
+    let codes = labels
+        .iter()
+        .map(|label| {
+            let label = label.unwrap_or_default();
+            label_class_code(label).ok_or_else(|| {
+                QuafferError::InvalidInput(format!("unknown classification label `{label}`"))
+            })
+        })
+        .collect::<Result<Vec<u8>>>()?;
+    Ok(dictionary
+        .keys()
+        .iter()
+        .map(|key| key.map(|key| codes[usize::from(key)]))
+        .collect())
+}
+
+#[derive(Default)]
+struct ColorColumns {
+    reds: Vec<Option<u16>>,
+    greens: Vec<Option<u16>>,
+    blues: Vec<Option<u16>>,
+}
+
+impl ColorColumns {
+    fn push(&mut self, color: Option<&Color>) {
+        self.reds.push(color.map(|color| color.red));
+        self.greens.push(color.map(|color| color.green));
+        self.blues.push(color.map(|color| color.blue));
+    }
+
+    fn drain(&mut self, legacy: bool) -> Vec<ArrayRef> {
+        vec![
+            int_column::<UInt16Type>(self.reds.drain(..), legacy),
+            int_column::<UInt16Type>(self.greens.drain(..), legacy),
+            int_column::<UInt16Type>(self.blues.drain(..), legacy),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct WaveformColumns {
+    descriptor_indices: Vec<Option<u8>>,
+    byte_offsets: Vec<Option<u64>>,
+    packet_sizes: Vec<Option<u32>>,
+    return_locations: Vec<Option<f32>>,
+    x_ts: Vec<Option<f32>>,
+    y_ts: Vec<Option<f32>>,
+    z_ts: Vec<Option<f32>>,
+}
+
+impl WaveformColumns {
+    fn push(&mut self, waveform: Option<&Waveform>) {
+        self.descriptor_indices
+            .push(waveform.map(|waveform| waveform.wave_packet_descriptor_index));
+        self.byte_offsets
+            .push(waveform.map(|waveform| waveform.byte_offset_to_waveform_data));
+        self.packet_sizes
+            .push(waveform.map(|waveform| waveform.waveform_packet_size_in_bytes));
+        self.return_locations
+            .push(waveform.map(|waveform| waveform.return_point_waveform_location));
+        self.x_ts.push(waveform.map(|waveform| waveform.x_t));
+        self.y_ts.push(waveform.map(|waveform| waveform.y_t));
+        self.z_ts.push(waveform.map(|waveform| waveform.z_t));
+    }
+
+    fn drain(&mut self, legacy: bool) -> Vec<ArrayRef> {
+        // u64 doesn't fit the Int64 widening, so it gets its own conversion
+        let byte_offsets: ArrayRef = if legacy {
+            Arc::new(
+                self.byte_offsets
+                    .drain(..)
+                    .map(|offset| offset.map(|offset| offset as i64))
+                    .collect::<Int64Array>(),
+            )
+        } else {
+            Arc::new(self.byte_offsets.drain(..).collect::<UInt64Array>())
+        };
+        vec![
+            int_column::<UInt8Type>(self.descriptor_indices.drain(..), legacy),
+            byte_offsets,
+            int_column::<UInt32Type>(self.packet_sizes.drain(..), legacy),
+            float_column(self.return_locations.drain(..), legacy),
+            float_column(self.x_ts.drain(..), legacy),
+            float_column(self.y_ts.drain(..), legacy),
+            float_column(self.z_ts.drain(..), legacy),
+        ]
+    }
+}
+
+fn drain_flags(flags: &mut Vec<bool>) -> ArrayRef {
+    Arc::new(flags.drain(..).map(Some).collect::<BooleanArray>())
+}
+
+// column accumulators for a single chunk of points, drained into a
+// RecordBatch every `batch_size` points so memory stays bounded
+// (values are kept in their LAS types and only widened on the way out)
+pub struct PointChunk {
+    // optional attributes only get collected when the output has columns for them
+    attributes: PointAttributes,
+    legacy_schema: bool,
+    x_coords: Vec<f64>,
+    y_coords: Vec<f64>,
+    z_coords: Vec<f64>,
+    fids: Vec<i64>,
+    intensities: Vec<u16>,
+    return_numbers: Vec<u8>,
+    number_of_returns: Vec<u8>,
+    // true = left to right (the LAS "positive" scan direction)
+    scan_directions: Vec<bool>,
+    class_codes: Vec<u8>,
+    // the old Debug-formatted labels ("Ground"), only for the legacy schema
+    class_names: Vec<String>,
+    scan_angles: Vec<f32>,
+    point_source_ids: Vec<u16>,
+    synthetic_flags: Vec<bool>,
+    key_point_flags: Vec<bool>,
+    withheld_flags: Vec<bool>,
+    overlap_flags: Vec<bool>,
+    edge_of_flight_line_flags: Vec<bool>,
+    user_data: Vec<u8>,
+    gps_times: Vec<Option<f64>>,
+    scanner_channels: Vec<u8>,
+    colors: ColorColumns,
+    nirs: Vec<Option<u16>>,
+    waveforms: WaveformColumns,
+    // this tile's descriptor for each of the output's extra bytes columns
+    // (None when the tile doesn't have that attribute)
+    extra_bytes: Vec<Option<ExtraBytesDescriptor>>,
+    extra_values: Vec<Vec<Option<ExtraValue>>>,
+}
+
+impl PointChunk {
+    fn with_capacity(
+        capacity: usize,
+        layout: &OutputLayout,
+        extra_bytes: Vec<Option<ExtraBytesDescriptor>>,
+    ) -> Self {
+        let attributes = layout.attributes;
+        let optional_capacity = |present: bool| if present { capacity } else { 0 };
+        let extra_values = extra_bytes
+            .iter()
+            .map(|_| Vec::with_capacity(capacity))
+            .collect();
+        PointChunk {
+            attributes,
+            legacy_schema: layout.legacy_schema,
+            x_coords: Vec::with_capacity(capacity),
+            y_coords: Vec::with_capacity(capacity),
+            z_coords: Vec::with_capacity(capacity),
+            fids: Vec::with_capacity(capacity),
+            intensities: Vec::with_capacity(capacity),
+            return_numbers: Vec::with_capacity(capacity),
+            number_of_returns: Vec::with_capacity(capacity),
+            scan_directions: Vec::with_capacity(capacity),
+            class_codes: Vec::with_capacity(optional_capacity(!layout.legacy_schema)),
+            class_names: Vec::with_capacity(optional_capacity(layout.legacy_schema)),
+            scan_angles: Vec::with_capacity(capacity),
+            point_source_ids: Vec::with_capacity(capacity),
+            synthetic_flags: Vec::with_capacity(capacity),
+            key_point_flags: Vec::with_capacity(capacity),
+            withheld_flags: Vec::with_capacity(capacity),
+            overlap_flags: Vec::with_capacity(capacity),
+            edge_of_flight_line_flags: Vec::with_capacity(capacity),
+            user_data: Vec::with_capacity(capacity),
+            gps_times: Vec::with_capacity(optional_capacity(attributes.gps_time)),
+            scanner_channels: Vec::with_capacity(optional_capacity(attributes.scanner_channel)),
+            colors: ColorColumns::default(),
+            nirs: Vec::with_capacity(optional_capacity(attributes.nir)),
+            waveforms: WaveformColumns::default(),
+            extra_bytes,
+            extra_values,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.fids.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.fids.is_empty()
+    }
+
+    // transform the buffered coords in place, all at once
+    fn reproject(&mut self, coord_transform: &CoordTransform) -> Result<()> {
+        coord_transform.transform_coords(
+            &mut self.x_coords,
+            &mut self.y_coords,
+            &mut self.z_coords,
+        )?;
+        Ok(())
+    }
+
+    fn push(&mut self, fid: i64, pnt: &Point) {
+        self.x_coords.push(pnt.x);
+        self.y_coords.push(pnt.y);
+        self.z_coords.push(pnt.z);
+        self.fids.push(fid);
+        self.intensities.push(pnt.intensity);
+        self.return_numbers.push(pnt.return_number);
+        self.number_of_returns.push(pnt.number_of_returns);
+        self.scan_directions.push(matches!(
+            pnt.scan_direction,
+            las::point::ScanDirection::LeftToRight
+        ));
+        if self.legacy_schema {
+            self.class_names.push(format!("{:?}", pnt.classification));
+        } else {
+            self.class_codes.push(u8::from(pnt.classification));
+        }
+        self.scan_angles.push(pnt.scan_angle);
+        self.point_source_ids.push(pnt.point_source_id);
+        self.synthetic_flags.push(pnt.is_synthetic);
+        self.key_point_flags.push(pnt.is_key_point);
+        self.withheld_flags.push(pnt.is_withheld);
+        self.overlap_flags.push(pnt.is_overlap);
+        self.edge_of_flight_line_flags
+            .push(pnt.is_edge_of_flight_line);
+        self.user_data.push(pnt.user_data);
+        if self.attributes.gps_time {
+            self.gps_times.push(pnt.gps_time);
+        }
+        if self.attributes.scanner_channel {
+            self.scanner_channels.push(pnt.scanner_channel);
+        }
+        if self.attributes.color {
+            self.colors.push(pnt.color.as_ref());
+        }
+        if self.attributes.nir {
+            self.nirs.push(pnt.nir);
+        }
+        if self.attributes.waveform {
+            self.waveforms.push(pnt.waveform.as_ref());
+        }
+        for (descriptor, values) in self.extra_bytes.iter().zip(&mut self.extra_values) {
+            values.push(
+                descriptor
+                    .as_ref()
+                    .and_then(|descriptor| descriptor.decode(&pnt.extra_bytes)),
+            );
+        }
     }
 
-    println!("total count {i}");
-    println!("Building GeoArrow arrays...");
+    // build a RecordBatch out of the buffered points, leaving the chunk empty
+    // (but with its allocations intact) so it can be refilled
+    // columns have to line up with `build_schema`
+    pub fn drain_to_record_batch(&mut self, layout: &OutputLayout) -> Result<RecordBatch> {
+        let xyz = layout.point_type.dimension() == Dimension::XYZ;
+        let legacy = layout.legacy_schema;
+        let mut point_builder = PointBuilder::new(layout.point_type.clone());
+        point_builder.reserve(self.len());
+        for idx in 0..self.len() {
+            let (x, y, z) = (self.x_coords[idx], self.y_coords[idx], self.z_coords[idx]);
+            if xyz {
+                point_builder.push_coord(Some(&XyzCoord { x, y, z }));
+            } else {
+                point_builder.push_point(Some(&geo::Point::new(x, y)));
+            }
+        }
+        let bbox_array: Option<ArrayRef> = layout.bbox_column.then(|| {
+            let x: ArrayRef = Arc::new(Float64Array::from(self.x_coords.clone()));
+            let y: ArrayRef = Arc::new(Float64Array::from(self.y_coords.clone()));
+            Arc::new(StructArray::new(
+                bbox_fields(),
+                vec![x.clone(), y.clone(), x, y],
+                None,
+            )) as ArrayRef
+        });
+        self.x_coords.clear();
+        self.y_coords.clear();
+        let points_arr_ref: ArrayRef = point_builder.finish().into_array_ref();
 
-    // Build the PointArray
-    let point_type = PointType::new(Dimension::XY, Default::default());
-    let data_type_point = point_type.clone().data_type();
-    let mut point_builder = PointBuilder::new(point_type);
-    point_builder.reserve(i as usize);
+        let mut columns: Vec<ArrayRef> = vec![
+            points_arr_ref,
+            Arc::new(Int64Array::from_iter_values(self.fids.drain(..))),
+        ];
+        // z only gets its own column when it isn't already part of the geometry
+        if xyz {
+            self.z_coords.clear();
+        } else {
+            columns.push(Arc::new(Float64Array::from_iter_values(
+                self.z_coords.drain(..),
+            )));
+        }
+        columns.extend(bbox_array);
 
-    for idx in 0..i as usize {
-        point_builder.push_point(Some(&geo::Point::new(x_coords[idx], y_coords[idx])));
+        let (scan_directions, classifications): (ArrayRef, ArrayRef) = if legacy {
+            (
+                Arc::new(StringArray::from_iter_values(
+                    self.scan_directions.drain(..).map(|left_to_right| {
+                        if left_to_right {
+                            "LeftToRight"
+                        } else {
+                            "RightToLeft"
+                        }
+                    }),
+                )),
+                Arc::new(StringArray::from_iter_values(self.class_names.drain(..))),
+            )
+        } else {
+            (
+                drain_flags(&mut self.scan_directions),
+                Arc::new(DictionaryArray::<UInt8Type>::try_new(
+                    UInt8Array::from_iter_values(self.class_codes.drain(..)),
+                    layout.class_labels.clone(),
+                )?),
+            )
+        };
+        let attribute_columns: Vec<ArrayRef> = vec![
+            int_column::<UInt16Type>(self.intensities.drain(..).map(Some), legacy),
+            int_column::<UInt8Type>(self.return_numbers.drain(..).map(Some), legacy),
+            int_column::<UInt8Type>(self.number_of_returns.drain(..).map(Some), legacy),
+            scan_directions,
+            classifications,
+            float_column(self.scan_angles.drain(..).map(Some), legacy),
+            int_column::<UInt16Type>(self.point_source_ids.drain(..).map(Some), legacy),
+            drain_flags(&mut self.synthetic_flags),
+            drain_flags(&mut self.key_point_flags),
+            drain_flags(&mut self.withheld_flags),
+            drain_flags(&mut self.overlap_flags),
+            drain_flags(&mut self.edge_of_flight_line_flags),
+            int_column::<UInt8Type>(self.user_data.drain(..).map(Some), legacy),
+        ];
+        columns.extend(attribute_columns);
+        let attributes = layout.attributes;
+        if attributes.gps_time {
+            columns.push(Arc::new(Float64Array::from_iter(self.gps_times.drain(..))));
+        }
+        if attributes.scanner_channel {
+            columns.push(int_column::<UInt8Type>(
+                self.scanner_channels.drain(..).map(Some),
+                legacy,
+            ));
+        }
+        if attributes.color {
+            columns.extend(self.colors.drain(legacy));
+        }
+        if attributes.nir {
+            columns.push(int_column::<UInt16Type>(self.nirs.drain(..), legacy));
+        }
+        if attributes.waveform {
+            columns.extend(self.waveforms.drain(legacy));
+        }
+        for (descriptor, values) in layout.extra_bytes.iter().zip(&mut self.extra_values) {
+            columns.push(descriptor.to_array(values.drain(..)));
+        }
+
+        let batch = RecordBatch::try_new(layout.schema.clone(), columns)?;
+        Ok(batch)
     }
+}
 
-    let point_array = point_builder.finish();
-    // Create metadata arrays
-    let fid_array = Int64Array::from(fids);
-    let z_array = Float64Array::from(z_coords);
-    let intensity_array = Int64Array::from(intensities);
-    let return_number_array = Int64Array::from(return_numbers);
-    let number_of_returns_array = Int64Array::from(number_of_returns);
-    let scan_direction_array = StringArray::from(scan_directions);
-    let classification_array = StringArray::from(classifications);
-    let scan_angle_array = Float64Array::from(scan_angles);
-    let point_source_id_array = Int64Array::from(point_source_ids);
-    let gps_time_array = Float64Array::from(gps_times);
+const BBOX_FIELD_NAMES: [&str; 4] = ["xmin", "ymin", "xmax", "ymax"];
 
+fn bbox_fields() -> Fields {
+    BBOX_FIELD_NAMES
+        .iter()
+        .map(|name| Field::new(*name, DataType::Float64, false))
+        .collect()
+}
+
+fn build_schema(
+    point_type: &PointType,
+    attributes: PointAttributes,
+    extra_bytes: &[ExtraBytesDescriptor],
+    legacy: bool,
+    bbox_column: bool,
+) -> Schema {
     let mut metadata = HashMap::new();
     metadata.insert(
         "ARROW:extension:name".to_string(),
         "geoarrow.point".to_string(),
     );
     metadata.insert("ARROW:extension:metadata".to_string(), "{}".to_string());
-    let geometry_field = Field::new("xy", data_type_point, false).with_metadata(metadata);
-
-    let schema = Schema::new(vec![
-        geometry_field,
-        Field::new("fid", DataType::Int64, false),
-        Field::new("z", DataType::Float64, false),
-        Field::new("intensity", DataType::Int64, false),
-        Field::new("return_number", DataType::Int64, false),
-        Field::new("number_of_returns", DataType::Int64, false),
-        Field::new("scan_direction", DataType::Utf8, false),
-        Field::new("classification", DataType::Utf8, false),
-        Field::new("scan_angle", DataType::Float64, false),
-        Field::new("point_source_id", DataType::Int64, false),
-        Field::new("gps_time", DataType::Float64, true),
+    let xyz = point_type.dimension() == Dimension::XYZ;
+    let geometry_field = Field::new(
+        geometry_column_name(xyz),
+        point_type.clone().data_type(),
+        false,
+    )
+    .with_metadata(metadata);
+
+    let mut fields = vec![geometry_field, Field::new("fid", DataType::Int64, false)];
+    if !xyz {
+        fields.push(Field::new("z", DataType::Float64, false));
+    }
+    if bbox_column {
+        fields.push(Field::new_struct(BBOX_COLUMN, bbox_fields(), false));
+    }
+    let (scan_direction, classification) = if legacy {
+        (
+            Field::new("scan_direction", DataType::Utf8, false),
+            Field::new("classification", DataType::Utf8, false),
+        )
+    } else {
+        (
+            Field::new("scan_direction", DataType::Boolean, false).with_metadata(HashMap::from([
+                (
+                    "description".to_string(),
+                    "true = left to right (positive scan direction)".to_string(),
+                ),
+            ])),
+            Field::new_dictionary("classification", DataType::UInt8, DataType::Utf8, false),
+        )
+    };
+    fields.extend(vec![
+        Field::new("intensity", las_type(DataType::UInt16, legacy), false),
+        Field::new("return_number", las_type(DataType::UInt8, legacy), false),
+        Field::new(
+            "number_of_returns",
+            las_type(DataType::UInt8, legacy),
+            false,
+        ),
+        scan_direction,
+        classification,
+        Field::new("scan_angle", las_type(DataType::Float32, legacy), false),
+        Field::new("point_source_id", las_type(DataType::UInt16, legacy), false),
+        Field::new("is_synthetic", DataType::Boolean, false),
+        Field::new("is_key_point", DataType::Boolean, false),
+        Field::new("is_withheld", DataType::Boolean, false),
+        Field::new("is_overlap", DataType::Boolean, false),
+        Field::new("is_edge_of_flight_line", DataType::Boolean, false),
+        Field::new("user_data", las_type(DataType::UInt8, legacy), false),
     ]);
+    // nullable, merged tiles without an attribute fill it with nulls
+    if attributes.gps_time {
+        fields.push(Field::new("gps_time", DataType::Float64, true));
+    }
+    if attributes.scanner_channel {
+        fields.push(Field::new(
+            "scanner_channel",
+            las_type(DataType::UInt8, legacy),
+            false,
+        ));
+    }
+    if attributes.color {
+        fields.extend(
+            ["red", "green", "blue"]
+                .map(|band| Field::new(band, las_type(DataType::UInt16, legacy), true)),
+        );
+    }
+    if attributes.nir {
+        fields.push(Field::new("nir", las_type(DataType::UInt16, legacy), true));
+    }
+    if attributes.waveform {
+        fields.extend(vec![
+            Field::new(
+                "wave_packet_descriptor_index",
+                las_type(DataType::UInt8, legacy),
+                true,
+            ),
+            Field::new(
+                "waveform_byte_offset",
+                las_type(DataType::UInt64, legacy),
+                true,
+            ),
+            Field::new(
+                "waveform_packet_size",
+                las_type(DataType::UInt32, legacy),
+                true,
+            ),
+            Field::new(
+                "waveform_return_location",
+                las_type(DataType::Float32, legacy),
+                true,
+            ),
+            Field::new("waveform_x_t", las_type(DataType::Float32, legacy), true),
+            Field::new("waveform_y_t", las_type(DataType::Float32, legacy), true),
+            Field::new("waveform_z_t", las_type(DataType::Float32, legacy), true),
+        ]);
+    }
+    for descriptor in extra_bytes {
+        // don't let a vendor attribute called e.g. `intensity` clash with ours
+        let taken = fields.iter().any(|field| field.name() == &descriptor.name);
+        let column_name = if taken {
+            format!("extra_{}", descriptor.name)
+        } else {
+            descriptor.name.clone()
+        };
+        fields.push(descriptor.field(&column_name));
+    }
+    Schema::new(fields)
+}
 
-    let points_arr_ref: ArrayRef = point_array.into_array_ref();
-    // Create RecordBatch
-    let batch = RecordBatch::try_new(
-        Arc::new(schema.clone()),
-        vec![
-            points_arr_ref,
-            Arc::new(fid_array),
-            Arc::new(z_array),
-            Arc::new(intensity_array),
-            Arc::new(return_number_array),
-            Arc::new(number_of_returns_array),
-            Arc::new(scan_direction_array),
-            Arc::new(classification_array),
-            Arc::new(scan_angle_array),
-            Arc::new(point_source_id_array),
-            Arc::new(gps_time_array),
-        ],
-    )?;
+fn geometry_column_name(xyz: bool) -> &'static str {
+    if xyz { XYZ_COLUMN } else { XY_COLUMN }
+}
 
-    println!("Writing GeoParquet to {outfile_path}...");
+// build a transform from the file's CRS to the requested one
+// (x/y/z all go through it, so a compound target like "EPSG:26915+5703"
+// shifts heights too when the source has a vertical datum)
+fn build_coord_transform(
+    source_crs: &WKTStringTransform,
+    target_definition: &str,
+) -> Result<(SpatialRef, CoordTransform)> {
+    let mut source = source_crs.spatial_ref()?;
+    let mut target = SpatialRef::from_definition(target_definition)?;
+    // keep everything x=easting/lon, y=northing/lat regardless of what the authority says
+    source.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+    target.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+    let coord_transform = CoordTransform::new(&source, &target)?;
+    Ok((target, coord_transform))
+}
 
-    let options = if wkt_transform.is_some() {
-        // build with CRS info
-        GeoParquetWriterOptionsBuilder::default()
-            .set_primary_column("xy".to_string())
-            .set_crs_transform(Box::new(wkt_transform.unwrap()))
-            .build()
-    } else {
-        // build w/o crs info (assumes WGS84)
-        GeoParquetWriterOptionsBuilder::default()
-            .set_primary_column("xy".to_string())
-            .build()
+// the arrow side of the output, shared by every chunk (and every tile when merging)
+#[derive(Debug, Clone)]
+pub struct OutputLayout {
+    point_type: PointType,
+    geometry_encoding: GeometryEncoding,
+    attributes: PointAttributes,
+    extra_bytes: Vec<ExtraBytesDescriptor>,
+    legacy_schema: bool,
+    bbox_column: bool,
+    // shared dictionary values for the classification column
+    class_labels: ArrayRef,
+    schema: SchemaRef,
+}
+
+impl OutputLayout {
+    pub fn new(
+        import_options: &ImportOptions,
+        attributes: PointAttributes,
+        extra_bytes: Vec<ExtraBytesDescriptor>,
+    ) -> Self {
+        let dimension = if import_options.xyz {
+            Dimension::XYZ
+        } else {
+            Dimension::XY
+        };
+        // WKB gets encoded from separated points on the way out
+        let coord_type = match import_options.geometry_encoding {
+            GeometryEncoding::Interleaved => CoordType::Interleaved,
+            GeometryEncoding::Separated | GeometryEncoding::Wkb => CoordType::Separated,
+        };
+        let point_type = PointType::new(dimension, Default::default()).with_coord_type(coord_type);
+        let legacy_schema = import_options.legacy_schema;
+        let schema = Arc::new(build_schema(
+            &point_type,
+            attributes,
+            &extra_bytes,
+            legacy_schema,
+            import_options.bbox_column,
+        ));
+        OutputLayout {
+            point_type,
+            geometry_encoding: import_options.geometry_encoding,
+            attributes,
+            extra_bytes,
+            legacy_schema,
+            bbox_column: import_options.bbox_column,
+            class_labels: classification_labels(),
+            schema,
+        }
+    }
+
+    pub fn geometry_column(&self) -> &'static str {
+        geometry_column_name(self.point_type.dimension() == Dimension::XYZ)
+    }
+}
+
+// what happened to a single tile during import
+#[derive(Debug, Clone)]
+pub struct ImportSummary {
+    pub input: String,
+    pub points_read: u64,
+    pub points_kept: u64,
+    pub crs: Option<String>,
+    pub bounds: Option<Bbox>,
+    pub elapsed: Duration,
+}
+
+// a hidden sibling of the real output that gets deleted unless it's persisted,
+// so a failed or interrupted write never leaves a half-written parquet that looks valid
+pub struct PartialFile {
+    pub path: PathBuf,
+    final_path: PathBuf,
+    persisted: bool,
+}
+
+impl PartialFile {
+    pub fn new(final_path: &Path) -> Self {
+        let file_name = final_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let path =
+            final_path.with_file_name(format!(".{file_name}.{}.partial", std::process::id()));
+        PartialFile {
+            path,
+            final_path: final_path.to_path_buf(),
+            persisted: false,
+        }
+    }
+
+    // move the finished file into place (rename is atomic on the same filesystem)
+    pub fn persist(mut self) -> Result<()> {
+        std::fs::rename(&self.path, &self.final_path)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+// owns the geoparquet encoder + parquet writer for one output file
+pub struct GpqWriter {
+    layout: OutputLayout,
+    outfile_path: String,
+    partial_file: PartialFile,
+    gpq_encoder: GeoParquetRecordBatchEncoder,
+    parquet_writer: ArrowWriter<File>,
+    // close out a row group after every batch, unless a row group size was asked for
+    flush_each_batch: bool,
+    // the clip region, recorded in the footer so it's clear the points are a subset
+    clip_json: Option<String>,
+    // headers of the tiles going into the file, for the footer
+    source_headers: Vec<Value>,
+    // bounds of everything written so far, from the bbox column
+    bounds: Option<Bbox>,
+    batch_count: usize,
+}
+
+fn f64_child<'a>(parent: &'a StructArray, name: &str) -> Option<&'a Float64Array> {
+    parent
+        .column_by_name(name)?
+        .as_primitive_opt::<Float64Type>()
+}
+
+// bounds of a batch's bbox column (None if it's empty or doesn't have one)
+fn batch_bounds(batch: &RecordBatch) -> Option<Bbox> {
+    let bbox = batch.column_by_name(BBOX_COLUMN)?.as_struct_opt()?;
+    let (xmin, ymin) = (f64_child(bbox, "xmin")?, f64_child(bbox, "ymin")?);
+    let (xmax, ymax) = (f64_child(bbox, "xmax")?, f64_child(bbox, "ymax")?);
+    let mut bounds = None;
+    for idx in 0..bbox.len() {
+        bounds = Some(Bbox::extend(bounds, xmin.value(idx), ymin.value(idx)));
+        bounds = Some(Bbox::extend(bounds, xmax.value(idx), ymax.value(idx)));
+    }
+    bounds
+}
+
+// grow `bounds` to cover the rows of a batch that's been written out
+pub fn extend_bounds(bounds: Option<Bbox>, batch: &RecordBatch) -> Option<Bbox> {
+    let Some(batch_bounds) = batch_bounds(batch) else {
+        return bounds;
+    };
+    let bounds = Bbox::extend(bounds, batch_bounds.minx, batch_bounds.miny);
+    Some(Bbox::extend(
+        Some(bounds),
+        batch_bounds.maxx,
+        batch_bounds.maxy,
+    ))
+}
+
+// fill in what the geoparquet writer leaves out of the `geo` metadata: the column's
+// encoding as we wrote it, plus the bbox column (GeoParquet 1.1 "covering") and file bbox.
+// shared by imports and the geoparquet outputs of query/export
+pub fn finish_geo_metadata(
+    mut geo_metadata: KeyValue,
+    geometry_column: &str,
+    geometry_encoding: GeometryEncoding,
+    bbox_column: bool,
+    bounds: Option<Bbox>,
+) -> Result<KeyValue> {
+    let Some(value) = &geo_metadata.value else {
+        return Ok(geo_metadata);
     };
+    let mut geo: Value = serde_json::from_str(value).map_err(|err| {
+        QuafferError::InvalidInput(format!(
+            "geoparquet wrote `geo` metadata we can't parse: {err}"
+        ))
+    })?;
+    // native point encodings and coverings only exist from 1.1 on
+    if bbox_column || geometry_encoding != GeometryEncoding::Wkb {
+        geo["version"] = Value::from("1.1.0");
+    }
+    let column = &mut geo["columns"][geometry_column];
+    column["encoding"] = Value::from(geometry_encoding.metadata_name());
+    if bbox_column {
+        let covering: serde_json::Map<String, Value> = BBOX_FIELD_NAMES
+            .iter()
+            .map(|name| (name.to_string(), serde_json::json!([BBOX_COLUMN, name])))
+            .collect();
+        column["covering"] = serde_json::json!({ BBOX_COLUMN: covering });
+    }
+    if let Some(bounds) = bounds {
+        column["bbox"] = serde_json::json!([bounds.minx, bounds.miny, bounds.maxx, bounds.maxy]);
+    }
+    geo_metadata.value = Some(geo.to_string());
+    Ok(geo_metadata)
+}
+
+impl GpqWriter {
+    pub fn try_new(
+        outfile_path: &str,
+        layout: OutputLayout,
+        output_crs: Option<&WKTStringTransform>,
+        import_options: &ImportOptions,
+    ) -> Result<Self> {
+        let encoding = match layout.geometry_encoding {
+            GeometryEncoding::Wkb => GeoParquetWriterEncoding::WKB,
+            GeometryEncoding::Separated | GeometryEncoding::Interleaved => {
+                GeoParquetWriterEncoding::GeoArrow
+            }
+        };
+        let mut options_builder = GeoParquetWriterOptionsBuilder::default()
+            .set_primary_column(layout.geometry_column().to_string())
+            .set_encoding(encoding);
+        // without CRS info readers assume WGS84
+        if let Some(wkt_transform) = output_crs {
+            options_builder = options_builder.set_crs_transform(Box::new(wkt_transform.clone()));
+        }
+        let options = options_builder.build();
+
+        let gpq_encoder = GeoParquetRecordBatchEncoder::try_new(&layout.schema, &options)?;
+
+        // Create Parquet writer with the target schema from the encoder
+        if !import_options.overwrite && Path::new(outfile_path).exists() {
+            return Err(QuafferError::InvalidInput(format!(
+                "{outfile_path} already exists, pass --force to overwrite it"
+            )));
+        }
+        // write next to the output and only move it into place once it's complete
+        let partial_file = PartialFile::new(Path::new(outfile_path));
+        let file = File::create(&partial_file.path)?;
+        let props = import_options.parquet.writer_properties(
+            gpq_encoder.target_schema().as_ref(),
+            import_options.batch_size,
+        );
+
+        let parquet_writer = ArrowWriter::try_new(file, gpq_encoder.target_schema(), Some(props))?;
+        let clip_json = import_options
+            .clip
+            .as_ref()
+            .map(|clip| clip.to_metadata_json())
+            .transpose()?;
+        Ok(GpqWriter {
+            layout,
+            outfile_path: outfile_path.to_string(),
+            partial_file,
+            gpq_encoder,
+            parquet_writer,
+            flush_each_batch: import_options.parquet.row_group_size.is_none(),
+            clip_json,
+            source_headers: Vec::new(),
+            bounds: None,
+            batch_count: 0,
+        })
+    }
+
+    pub fn add_source_header(&mut self, header: Value) {
+        self.source_headers.push(header);
+    }
+
+    pub fn write_chunk(&mut self, chunk: &mut PointChunk) -> Result<()> {
+        let batch = chunk.drain_to_record_batch(&self.layout)?;
+        self.write_batch(&batch)
+    }
+
+    // encode a batch through the geoparquet encoder and hand it to the parquet writer
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.bounds = extend_bounds(self.bounds, batch);
+        let encoded_batch = self.gpq_encoder.encode_record_batch(batch)?;
+        self.parquet_writer.write(&encoded_batch)?;
+        // close out the row group now so its buffers get flushed to disk
+        // (with an explicit row group size the writer does that itself once it fills up)
+        if self.flush_each_batch {
+            self.parquet_writer.flush()?;
+        }
+        self.batch_count += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        // Add GeoParquet metadata and finish
+        let kv_metadata = finish_geo_metadata(
+            self.gpq_encoder.into_keyvalue()?,
+            self.layout.geometry_column(),
+            self.layout.geometry_encoding,
+            self.layout.bbox_column,
+            self.bounds,
+        )?;
+        self.parquet_writer.append_key_value_metadata(kv_metadata);
+        if let Some(clip_json) = self.clip_json.take() {
+            self.parquet_writer
+                .append_key_value_metadata(KeyValue::new(CLIP_METADATA_KEY.to_string(), clip_json));
+        }
+        if !self.source_headers.is_empty() {
+            let headers = Value::Array(std::mem::take(&mut self.source_headers));
+            self.parquet_writer.append_key_value_metadata(KeyValue::new(
+                LAS_HEADER_METADATA_KEY.to_string(),
+                headers.to_string(),
+            ));
+        }
+        self.parquet_writer.close()?;
+        self.partial_file.persist()?;
+        println!(
+            "Wrote {} batches to {}",
+            self.batch_count, self.outfile_path
+        );
+        Ok(())
+    }
+}
+
+// an opened point cloud file with its CRS sorted out, ready to stream points from
+pub struct LazTile {
+    pub filename: String,
+    source: Box<dyn PointSource>,
+    // the CRS points will be in once they come out of `stream_chunks`
+    pub output_crs: Option<WKTStringTransform>,
+    coord_transform: Option<CoordTransform>,
+    // custom per-point attributes from the Extra Bytes VLR
+    pub extra_bytes: Vec<ExtraBytesDescriptor>,
+    // clip shape in the tile's own (source) CRS, points get tested before reprojecting
+    clip: Option<PreparedClip>,
+}
+
+impl LazTile {
+    pub fn open(filename: &str, import_options: &ImportOptions) -> Result<Self> {
+        println!("Opening point cloud file at {filename}");
+        let source = open_source(Path::new(filename), &import_options.source)?;
+        // a CRS given on the command line wins over whatever the file says
+        let source_crs = match &import_options.source.crs {
+            Some(definition) => Some(WKTStringTransform::from_spatial_ref(
+                &SpatialRef::from_definition(definition)?,
+            )?),
+            None => source.crs()?,
+        };
+        let extra_bytes = source.extra_bytes()?;
+        if source_crs.is_none() {
+            if import_options.require_crs {
+                return Err(QuafferError::Crs(format!(
+                    "couldn't determine a CRS for {filename}"
+                )));
+            }
+            eprintln!(
+                "WARNING: couldn't determine a CRS for {filename}, \
+                the output won't have one and readers will assume WGS84 \
+                (pass --source-crs to set it)"
+            );
+        }
+
+        let clip = import_options
+            .clip
+            .as_ref()
+            .map(|clip| clip.prepare(source_crs.as_ref()))
+            .transpose()?;
+
+        // when reprojecting, the output gets the target CRS instead of the source one
+        let (output_crs, coord_transform) = match &import_options.target_crs {
+            Some(target_definition) => {
+                let source_crs = source_crs.ok_or_else(|| {
+                    QuafferError::Crs(format!(
+                        "can't reproject to {target_definition}, {filename} has no CRS to start from"
+                    ))
+                })?;
+                let (target, coord_transform) =
+                    build_coord_transform(&source_crs, target_definition)?;
+                println!("Reprojecting points to {target_definition}");
+                (
+                    Some(WKTStringTransform::from_spatial_ref(&target)?),
+                    Some(coord_transform),
+                )
+            }
+            None => (source_crs, None),
+        };
+
+        Ok(LazTile {
+            filename: filename.to_string(),
+            source,
+            output_crs,
+            coord_transform,
+            extra_bytes,
+            clip,
+        })
+    }
+
+    pub fn number_of_points(&self) -> u64 {
+        self.source.number_of_points()
+    }
+
+    pub fn attributes(&self) -> PointAttributes {
+        self.source.attributes()
+    }
+
+    // None for formats without a LAS header
+    pub fn header_json(&self) -> Option<Value> {
+        self.source.header_json(&self.filename)
+    }
+
+    // read the tile `batch_size` points at a time, handing each filtered and
+    // reprojected chunk (with the columns `layout` asks for) to `on_chunk`,
+    // which is expected to drain it
+    // fids start at `fid_offset` so merged tiles don't collide
+    pub fn stream_chunks(
+        mut self,
+        import_options: &ImportOptions,
+        layout: &OutputLayout,
+        fid_offset: i64,
+        mut on_chunk: impl FnMut(&mut PointChunk) -> Result<()>,
+    ) -> Result<ImportSummary> {
+        let start = Instant::now();
+        let batch_size = import_options.batch_size;
+        let mut summary = ImportSummary {
+            input: self.filename.clone(),
+            points_read: 0,
+            points_kept: 0,
+            crs: self.output_crs.as_ref().map(|crs| crs.label()),
+            bounds: None,
+            elapsed: Duration::ZERO,
+        };
+        let mut handle_chunk = |chunk: &mut PointChunk, summary: &mut ImportSummary| {
+            if let Some(coord_transform) = &self.coord_transform {
+                chunk.reproject(coord_transform)?;
+            }
+            for (x, y) in chunk.x_coords.iter().zip(&chunk.y_coords) {
+                summary.bounds = Some(Bbox::extend(summary.bounds, *x, *y));
+            }
+            on_chunk(chunk)
+        };
+
+        // only ever hold `batch_size` points in memory at once
+        // line this tile's extra bytes up with the output's columns
+        let extra_bytes = layout
+            .extra_bytes
+            .iter()
+            .map(|column| {
+                self.extra_bytes
+                    .iter()
+                    .find(|descriptor| descriptor.name == column.name)
+                    .cloned()
+            })
+            .collect();
+        let mut chunk = PointChunk::with_capacity(batch_size, layout, extra_bytes);
+        let mut sampler = Sampler::new(import_options.sampling, import_options.seed);
+        // sorting means holding the whole (filtered) tile until it's all been read
+        let mut sorter = SpatialSorter::new(import_options.sort);
+        let max_points = import_options.max_points.map(|max| max.max(0) as u64);
+        if max_points == Some(0) {
+            summary.elapsed = start.elapsed();
+            return Ok(summary);
+        }
+        // fids are the point's position in the file (plus the offset),
+        // so the same point always gets the same fid no matter what's filtered out
+        self.source.read_points(&mut |pnt: Point| -> Result<bool> {
+            let fid = fid_offset + summary.points_read as i64;
+            summary.points_read += 1;
+            // if the point doesn't pass the filter, dip early
+            if !import_options.filter.keep(&pnt) {
+                return Ok(true);
+            }
+            if let Some(clip) = &self.clip
+                && !clip.contains(pnt.x, pnt.y)
+            {
+                return Ok(true);
+            }
+            let Some((fid, pnt)) = sampler.offer(fid, pnt) else {
+                return Ok(true);
+            };
+            summary.points_kept += 1;
+            if let Some(sorter) = &mut sorter {
+                sorter.push(fid, pnt);
+            } else {
+                chunk.push(fid, &pnt);
+                if chunk.len() >= batch_size {
+                    handle_chunk(&mut chunk, &mut summary)?;
+                    println!("{}: {} points so far", self.filename, summary.points_kept);
+                }
+            }
+            if max_points == Some(summary.points_kept) {
+                println!("Hit limit for max number of points, continuing...");
+                return Ok(false);
+            }
+            Ok(true)
+        })?;
+        // reservoir sampled points only get written once the whole file's been seen
+        for (fid, pnt) in sampler.finish() {
+            summary.points_kept += 1;
+            if let Some(sorter) = &mut sorter {
+                sorter.push(fid, pnt);
+            } else {
+                chunk.push(fid, &pnt);
+                if chunk.len() >= batch_size {
+                    handle_chunk(&mut chunk, &mut summary)?;
+                }
+            }
+        }
+        if let Some(sorter) = sorter {
+            println!(
+                "{}: sorting {} points ({})",
+                self.filename, summary.points_kept, import_options.sort
+            );
+            for (fid, pnt) in sorter.finish() {
+                chunk.push(fid, &pnt);
+                if chunk.len() >= batch_size {
+                    handle_chunk(&mut chunk, &mut summary)?;
+                }
+            }
+        }
+        // flush whatever's left over
+        if !chunk.is_empty() {
+            handle_chunk(&mut chunk, &mut summary)?;
+        }
 
-    let mut gpq_encoder = GeoParquetRecordBatchEncoder::try_new(&schema, &options)?;
+        summary.elapsed = start.elapsed();
+        Ok(summary)
+    }
+}
 
-    // Create Parquet writer with the target schema from the encoder
-    let file = File::create(&outfile_path)?;
-    let props = WriterProperties::builder()
-        .set_compression(Compression::SNAPPY)
-        .build();
+// catch bad option values before any files get opened
+pub fn check_options(import_options: &ImportOptions) -> Result<()> {
+    if import_options.batch_size == 0 {
+        return Err(QuafferError::InvalidInput(
+            "batch size must be greater than zero".to_string(),
+        ));
+    }
+    if import_options.geometry_encoding == GeometryEncoding::Interleaved {
+        eprintln!(
+            "WARNING: interleaved points aren't standard GeoParquet (its native point \
+             encoding is the separated layout), other readers may not be able to read them"
+        );
+    }
+    import_options.sampling.validate()?;
+    import_options.parquet.validate()
+}
 
-    let mut parquet_writer = ArrowWriter::try_new(file, gpq_encoder.target_schema(), Some(props))?;
+// open up a point cloud file (.laz/.las, .ply, .xyz/.csv or .e57)
+// (testing with USGS data)
+// and stream it into a geoparquet file, `batch_size` points at a time
+pub fn read_laz_to_gpq(
+    filename: String,
+    outfile_path: String,
+    import_options: &ImportOptions,
+) -> Result<ImportSummary> {
+    check_options(import_options)?;
+    let tile = LazTile::open(&filename, import_options)?;
+    let layout = OutputLayout::new(import_options, tile.attributes(), tile.extra_bytes.clone());
+
+    println!(
+        "Writing GeoParquet to {outfile_path} in batches of {} points...",
+        import_options.batch_size
+    );
+    let mut writer = GpqWriter::try_new(
+        &outfile_path,
+        layout.clone(),
+        tile.output_crs.as_ref(),
+        import_options,
+    )?;
+    if let Some(header) = tile.header_json() {
+        writer.add_source_header(header);
+    }
+    let summary = tile.stream_chunks(import_options, &layout, 0, |chunk| {
+        writer.write_chunk(chunk)
+    })?;
+    writer.finish()?;
 
-    // Encode and write the batch
-    let encoded_batch = gpq_encoder.encode_record_batch(&batch)?;
-    parquet_writer.write(&encoded_batch)?;
+    println!(
+        "Done! Wrote {} points to {outfile_path}",
+        summary.points_kept
+    );
 
-    // Add GeoParquet metadata and finish
-    let kv_metadata = gpq_encoder.into_keyvalue()?;
-    parquet_writer.append_key_value_metadata(kv_metadata);
-    parquet_writer.close()?;
+    Ok(summary)
+}
 
-    println!("Done! Wrote {i} points to {outfile_path}");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::point::Classification;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 
-    Ok(())
+    #[test]
+    fn classification_round_trips_through_parquet() {
+        let path = std::env::temp_dir().join(format!(
+            "quaffer_classification_{}.parquet",
+            std::process::id()
+        ));
+        let import_options = ImportOptions {
+            overwrite: true,
+            ..Default::default()
+        };
+        let layout = OutputLayout::new(&import_options, PointAttributes::default(), Vec::new());
+        let mut writer = GpqWriter::try_new(
+            path.to_str().unwrap(),
+            layout.clone(),
+            None,
+            &import_options,
+        )
+        .unwrap();
+        // water shows up first, so the file's dictionary has it at key 0 and ground at 1
+        let classes: Vec<u8> = vec![9, 2, 9, 6, 2];
+        let mut chunk = PointChunk::with_capacity(classes.len(), &layout, Vec::new());
+        for (fid, class) in classes.iter().enumerate() {
+            let point = Point {
+                classification: Classification::new(*class).unwrap(),
+                ..Default::default()
+            };
+            chunk.push(fid as i64, &point);
+        }
+        writer.write_chunk(&mut chunk).unwrap();
+        writer.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut codes = Vec::new();
+        for batch in reader {
+            let batch = batch.unwrap();
+            let column = batch.column_by_name("classification").unwrap();
+            let decoded = decode_class_codes(column.as_dictionary::<UInt8Type>()).unwrap();
+            codes.extend(decoded.values().iter().copied());
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(codes, classes);
+    }
+
+    #[test]
+    fn class_labels_decode_to_their_codes() {
+        for code in 0..=u8::MAX {
+            assert_eq!(label_class_code(&class_label(code)), Some(code));
+        }
+        assert_eq!(label_class_code("not_a_class"), None);
+    }
 }
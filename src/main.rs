@@ -1,15 +1,91 @@
 // get our modules
 #[cfg(feature = "laz_import")]
+mod batch_import;
+#[cfg(any(feature = "laz_import", feature = "parquet"))]
+mod bbox;
+#[cfg(feature = "laz_import")]
+mod clip;
+#[cfg(feature = "laz_import")]
+mod e57_source;
+#[cfg(any(feature = "laz_import", feature = "parquet"))]
+mod error;
+#[cfg(feature = "laz_import")]
+mod export;
+#[cfg(feature = "laz_import")]
+mod extra_bytes;
+#[cfg(feature = "laz_import")]
+mod geotiff_keys;
+#[cfg(feature = "laz_import")]
+mod gpq_to_laz;
+#[cfg(feature = "laz_import")]
+mod info;
+#[cfg(feature = "laz_import")]
+mod las_header;
+#[cfg(feature = "laz_import")]
 mod laz_to_gpq;
+#[cfg(feature = "laz_import")]
+mod parquet_options;
+#[cfg(feature = "laz_import")]
+mod ply_source;
+#[cfg(feature = "laz_import")]
+mod point_filter;
+#[cfg(feature = "laz_import")]
+mod point_output;
+#[cfg(feature = "laz_import")]
+mod point_source;
+#[cfg(feature = "laz_import")]
+mod query;
+#[cfg(feature = "laz_import")]
+mod sampling;
+#[cfg(feature = "laz_import")]
+mod spatial_sort;
+#[cfg(feature = "laz_import")]
+mod xyz_source;
 
 #[cfg(feature = "laz_import")]
-use laz_to_gpq::read_laz_to_gpq;
+use batch_import::import_tiles;
+#[cfg(feature = "laz_import")]
+use clip::ClipRegion;
+#[cfg(feature = "laz_import")]
+use export::export;
+#[cfg(feature = "laz_import")]
+use gpq_to_laz::gpq_to_laz;
+#[cfg(feature = "laz_import")]
+use info::info;
+#[cfg(feature = "laz_import")]
+use laz_to_gpq::{DEFAULT_BATCH_SIZE, ImportOptions};
+#[cfg(feature = "laz_import")]
+use parquet_options::{
+    ColumnEncoding, CompressionChoice, GeometryEncoding, ParquetOptions, StatisticsLevel,
+};
+#[cfg(feature = "laz_import")]
+use point_filter::{ClassCode, FlagFilter, PointFilter};
+#[cfg(feature = "laz_import")]
+use point_output::OutputFormat;
+#[cfg(feature = "laz_import")]
+use point_source::{PointField, SourceFormat, SourceOptions};
+#[cfg(feature = "laz_import")]
+use query::{QueryOptions, parse_predicates, query};
+#[cfg(feature = "laz_import")]
+use sampling::{Sampling, pick_seed};
+#[cfg(feature = "laz_import")]
+use spatial_sort::SpatialSort;
+#[cfg(feature = "laz_import")]
+use xyz_source::CsvOptions;
 
+#[cfg(feature = "parquet")]
+mod footer_keys;
+#[cfg(feature = "parquet")]
+mod pruning;
 #[cfg(feature = "parquet")]
 mod read_parq;
+#[cfg(feature = "parquet")]
+mod stats;
 
 #[cfg(feature = "parquet")]
 use read_parq::read;
+#[cfg(feature = "parquet")]
+use stats::stats;
 #[cfg(feature = "wasm_viz")]
 mod bevy_viz;
 #[cfg(feature = "wasm_viz")]
@@ -24,6 +100,8 @@ mod bevy_blob_loader_source;
 mod bevy_web_file_drop;
 #[cfg(feature = "cli")]
 use clap::{Parser, Subcommand};
+#[cfg(all(feature = "cli", feature = "laz_import"))]
+use std::path::PathBuf;
 
 // Define args for CLI
 #[cfg(feature = "cli")]
@@ -38,23 +116,231 @@ struct PointQuaffer {
 #[cfg(feature = "cli")]
 #[derive(Subcommand)]
 enum ProcessType {
-    // Importing .laz files to the geoparquet schema/file
+    // Importing point clouds (.laz/.las, .ply, .xyz/.csv/.pts, .e57) to the geoparquet schema/file
     #[cfg(feature = "laz_import")]
     LazImport {
-        // path to .laz file
-        input: String,
-        // Only get points classified as ground
+        // paths to point cloud files, directories of them, or globs like "tiles/*.laz"
+        #[arg(required = true)]
+        input: Vec<String>,
+        // Input format: las, ply, csv (also xyz/txt/pts) or e57
+        // (defaults to each file's extension)
+        #[arg(long)]
+        input_format: Option<SourceFormat>,
+        // CRS of the input points (e.g. EPSG:32615), overrides the one in the file.
+        // XYZ/CSV files don't carry one, so this is the only way to give them a CRS
+        #[arg(long)]
+        source_crs: Option<String>,
+        // What each column of an XYZ/CSV file holds, e.g. x,y,z,intensity,skip,classification
+        // (defaults to the header row, or x,y,z without one)
+        #[arg(long, value_delimiter = ',')]
+        csv_columns: Vec<PointField>,
+        // Column delimiter for XYZ/CSV files (defaults to comma, semicolon or tab
+        // if the first line has one, otherwise whitespace)
+        #[arg(long)]
+        csv_delimiter: Option<char>,
+        // Where to write: a directory for per-tile outputs, or a file path
+        // (for a single tile or --merge). Defaults to next to each input
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        // Merge every tile into the single file given by --output
+        // instead of writing one output per tile
+        #[arg(long, requires = "output")]
+        merge: bool,
+        // Overwrite output files that already exist
+        #[arg(long)]
+        force: bool,
+        // Only get points classified as ground (same as --keep-class ground)
         #[arg(short, long)]
         filter_to_ground: bool,
-        // Stop collecting points at this number if provided
+        // Only keep these ASPRS classes, as codes or names (e.g. ground,water or 2,9)
+        #[arg(long, value_delimiter = ',')]
+        keep_class: Vec<ClassCode>,
+        // Throw away these ASPRS classes, as codes or names (e.g. noise,high_noise or 7,18)
+        #[arg(long, value_delimiter = ',')]
+        drop_class: Vec<ClassCode>,
+        // What to do with withheld points: keep, drop, or only
+        #[arg(long, default_value_t = FlagFilter::Keep)]
+        withheld: FlagFilter,
+        // What to do with overlap points: keep, drop, or only
+        #[arg(long, default_value_t = FlagFilter::Keep)]
+        overlap: FlagFilter,
+        // What to do with synthetic points: keep, drop, or only
+        #[arg(long, default_value_t = FlagFilter::Keep)]
+        synthetic: FlagFilter,
+        // Only keep points inside this box, as minx,miny,maxx,maxy
+        // (in the file's CRS unless --clip-crs is given)
+        #[arg(long, allow_hyphen_values = true, conflicts_with = "clip_polygon")]
+        clip_bbox: Option<String>,
+        // Only keep points inside the polygon(s) in this GeoJSON or WKT file
+        // (in the file's CRS unless --clip-crs is given)
+        #[arg(long)]
+        clip_polygon: Option<PathBuf>,
+        // CRS the clip bbox/polygon is in (e.g. EPSG:4326)
+        #[arg(long)]
+        clip_crs: Option<String>,
+        // Stop once this many points have been kept (per tile)
         #[arg(short, long, default_value=None)]
         max_point_count: Option<i64>,
+        // Randomly keep this fraction of the points (0-1], e.g. 0.01 for 1%
+        #[arg(long, conflicts_with = "random_sample")]
+        sample_rate: Option<f64>,
+        // Keep exactly this many points (per tile), picked at random from the whole file
+        #[arg(long, conflicts_with = "max_point_count")]
+        random_sample: Option<usize>,
+        // Seed for --sample-rate/--random-sample, to get the same points on every run
+        #[arg(long)]
+        seed: Option<u64>,
+        // Number of points to buffer per batch (and parquet row group),
+        // keeps memory use bounded for large tiles
+        #[arg(short, long, default_value_t = DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+        // Write XY points with elevation in a separate `z` column
+        // (the older layout) instead of true 3D XYZ points
+        #[arg(long)]
+        xy_only: bool,
+        // Point geometry layout: separated (x/y/z struct, GeoParquet 1.1 native),
+        // wkb (for GeoParquet 1.0 readers) or interleaved (one list per point,
+        // not standard GeoParquet, so other readers may not read it)
+        #[arg(long, default_value_t = GeometryEncoding::default())]
+        geometry_encoding: GeometryEncoding,
+        // Write the old schema (Int64/Float64 numbers, string classification and
+        // scan direction) instead of LAS-typed columns, for existing consumers
+        #[arg(long)]
+        legacy_schema: bool,
+        // Skip the GeoParquet 1.1 `bbox` covering column (readers use it to skip row groups)
+        #[arg(long)]
+        no_bbox: bool,
+        // Sort points so each row group covers a compact area: none, hilbert, morton, or
+        // grid-time (coarse hilbert cells, then gps_time). Holds a whole tile in memory,
+        // and merged tiles are each sorted on their own
+        #[arg(long, default_value_t = SpatialSort::default())]
+        sort: SpatialSort,
+        // Parquet compression: none, snappy, lz4, zstd[:level], gzip[:level] or brotli[:level]
+        #[arg(long, default_value_t = CompressionChoice::default())]
+        compression: CompressionChoice,
+        // Per-column parquet encodings as COLUMN=ENCODING, e.g. fid=delta_binary_packed
+        // or xyz.x=byte_stream_split (nested point coords use dotted paths)
+        #[arg(long, value_delimiter = ',')]
+        encoding: Vec<ColumnEncoding>,
+        // Use BYTE_STREAM_SPLIT for every float column (coords, gps_time, ...),
+        // usually compresses them much better
+        #[arg(long)]
+        byte_stream_split: bool,
+        // Use DELTA_BINARY_PACKED for the fid column. gps_time is a double, which
+        // DELTA_BINARY_PACKED can't encode (it's integers only), so it's left out:
+        // --encoding gps_time=byte_stream_split (or --byte-stream-split) is the one for it
+        #[arg(long)]
+        delta_fids: bool,
+        // Turn off parquet dictionary encoding
+        #[arg(long)]
+        no_dictionary: bool,
+        // Column statistics to write: none, chunk (per row group) or page
+        // (per page too, lets readers skip more data)
+        #[arg(long, default_value_t = StatisticsLevel::Page)]
+        statistics: StatisticsLevel,
+        // Rows per parquet row group (defaults to one row group per batch)
+        #[arg(long)]
+        row_group_size: Option<usize>,
+        // Fail instead of warning when no CRS can be found in the file
+        #[arg(long)]
+        require_crs: bool,
+        // Reproject points into this CRS (e.g. EPSG:4326, EPSG:32615,
+        // or a compound like EPSG:26915+5703 to also shift heights)
+        #[arg(short, long)]
+        target_crs: Option<String>,
+    },
+    // Writing an imported geoparquet file back out to .las/.laz
+    #[cfg(feature = "laz_import")]
+    GpqToLaz {
+        // path to geoparquet file (created by laz-import)
+        input: String,
+        // path to write, compressed if it ends in .laz
+        output: String,
+        // Coordinate resolution (defaults to 0.001, or 1e-7 for lat/lon data)
+        #[arg(long)]
+        scale: Option<f64>,
+        // Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    // Writing an imported geoparquet file out as CSV, GeoJSON, GeoJSONSeq, FlatGeobuf or PLY
+    #[cfg(feature = "laz_import")]
+    Export {
+        // path to geoparquet file (created by laz-import)
+        input: String,
+        // File to write, stdout if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        // Output format: csv, geojson, geojsonseq, fgb, ply or parquet
+        // (defaults to the output's extension, or csv on stdout)
+        #[arg(long)]
+        format: Option<OutputFormat>,
+        // Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    // Summarizing a .las/.laz or geoparquet file from its header/footer, without reading points
+    #[cfg(feature = "laz_import")]
+    Info {
+        // path to a .las/.laz or geoparquet file
+        input: PathBuf,
+        // Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    // Pulling the points that match a bbox/polygon, z range, classes and attribute
+    // predicates out of a geoparquet file, into a new file or stdout
+    #[cfg(feature = "laz_import")]
+    Query {
+        // path to geoparquet file (created by laz-import)
+        input: String,
+        // Only points in minx,miny,maxx,maxy (in the file's CRS)
+        #[arg(long, allow_hyphen_values = true, conflicts_with = "polygon")]
+        bbox: Option<bbox::Bbox>,
+        // Only points inside the polygon(s) in this GeoJSON or WKT file (in the file's CRS)
+        #[arg(long)]
+        polygon: Option<PathBuf>,
+        // Only points at or above this elevation
+        #[arg(long, allow_hyphen_values = true)]
+        z_min: Option<f64>,
+        // Only points at or below this elevation
+        #[arg(long, allow_hyphen_values = true)]
+        z_max: Option<f64>,
+        // Only these ASPRS classes, as codes or names (e.g. ground,water or 2,9)
+        #[arg(long, value_delimiter = ',')]
+        class: Vec<ClassCode>,
+        // Attribute predicates joined with AND, e.g. "intensity > 200 AND return_number = 1"
+        #[arg(long = "where")]
+        filter: Option<String>,
+        // File to write, stdout if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        // Output format: parquet, csv, geojson, geojsonseq, fgb or ply
+        // (defaults to the output's extension, or csv on stdout)
+        #[arg(long)]
+        format: Option<OutputFormat>,
+        // Overwrite the output file if it already exists
+        #[arg(long)]
+        force: bool,
     },
     // Reading an imported set of data
     #[cfg(feature = "parquet")]
     Read {
         // path to geoparquet file (created by laz-import)
         input: String,
+        // Only read points in minx,miny,maxx,maxy (in the file's CRS),
+        // skipping row groups that can't have any
+        #[arg(long, allow_hyphen_values = true)]
+        bbox: Option<bbox::Bbox>,
+    },
+    // Per-column distributions, class/return breakdowns, density and a z histogram
+    #[cfg(feature = "parquet")]
+    Stats {
+        // path to geoparquet file (created by laz-import)
+        input: String,
+        // Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
     },
     Hello,
 }
@@ -74,25 +360,168 @@ fn main() -> Result<()> {
             #[cfg(feature = "laz_import")]
             ProcessType::LazImport {
                 input,
+                input_format,
+                source_crs,
+                csv_columns,
+                csv_delimiter,
+                output,
+                merge,
+                force,
                 filter_to_ground,
+                keep_class,
+                drop_class,
+                withheld,
+                overlap,
+                synthetic,
+                clip_bbox,
+                clip_polygon,
+                clip_crs,
                 max_point_count,
+                sample_rate,
+                random_sample,
+                seed,
+                batch_size,
+                xy_only,
+                geometry_encoding,
+                legacy_schema,
+                no_bbox,
+                sort,
+                compression,
+                encoding,
+                byte_stream_split,
+                delta_fids,
+                no_dictionary,
+                statistics,
+                row_group_size,
+                require_crs,
+                target_crs,
             } => {
-                let mut outfile_path = input.trim_end_matches(".laz").to_string();
+                let mut keep_classes = keep_class.clone();
                 if *filter_to_ground {
-                    outfile_path += "_filter";
+                    // ASPRS ground
+                    keep_classes.push(ClassCode(2));
                 }
-                outfile_path = format!("{}.parquet", outfile_path);
-                read_laz_to_gpq(
-                    input.to_string(),
-                    *filter_to_ground,
-                    *max_point_count,
-                    outfile_path,
-                )
+                let filter = PointFilter {
+                    withheld: *withheld,
+                    overlap: *overlap,
+                    synthetic: *synthetic,
+                    ..PointFilter::new(&keep_classes, drop_class)
+                };
+                let clip = match (clip_bbox, clip_polygon) {
+                    (Some(bbox), _) => Some(ClipRegion::from_bbox(bbox, clip_crs.clone())?),
+                    (None, Some(path)) => Some(ClipRegion::from_file(path, clip_crs.clone())?),
+                    (None, None) => None,
+                };
+                let sampling = match (sample_rate, random_sample) {
+                    (Some(rate), _) => Sampling::Rate(*rate),
+                    (None, Some(size)) => Sampling::Random(*size),
+                    (None, None) => Sampling::All,
+                };
+                let seed = match sampling {
+                    Sampling::All => 0,
+                    _ => pick_seed(*seed),
+                };
+                let import_options = ImportOptions {
+                    filter,
+                    clip,
+                    max_points: *max_point_count,
+                    sampling,
+                    seed,
+                    batch_size: *batch_size,
+                    xyz: !*xy_only,
+                    geometry_encoding: *geometry_encoding,
+                    require_crs: *require_crs,
+                    target_crs: target_crs.clone(),
+                    overwrite: *force,
+                    legacy_schema: *legacy_schema,
+                    bbox_column: !*no_bbox,
+                    sort: *sort,
+                    parquet: ParquetOptions {
+                        compression: *compression,
+                        column_encodings: encoding.clone(),
+                        byte_stream_split: *byte_stream_split,
+                        delta_fids: *delta_fids,
+                        dictionary: !*no_dictionary,
+                        statistics: *statistics,
+                        row_group_size: *row_group_size,
+                    },
+                    source: SourceOptions {
+                        format: *input_format,
+                        crs: source_crs.clone(),
+                        csv: CsvOptions {
+                            columns: csv_columns.clone(),
+                            delimiter: *csv_delimiter,
+                        },
+                    },
+                };
+                import_tiles(input, output.as_deref(), *merge, &import_options)?;
+                Ok(())
             }
 
+            #[cfg(feature = "laz_import")]
+            ProcessType::GpqToLaz {
+                input,
+                output,
+                scale,
+                force,
+            } => {
+                gpq_to_laz(input, output, *scale, *force)?;
+                Ok(())
+            }
+            #[cfg(feature = "laz_import")]
+            ProcessType::Export {
+                input,
+                output,
+                format,
+                force,
+            } => {
+                export(input, output.as_deref(), *format, *force)?;
+                Ok(())
+            }
+            #[cfg(feature = "laz_import")]
+            ProcessType::Info { input, json } => {
+                info(input, *json)?;
+                Ok(())
+            }
+            #[cfg(feature = "laz_import")]
+            ProcessType::Query {
+                input,
+                bbox,
+                polygon,
+                z_min,
+                z_max,
+                class,
+                filter,
+                output,
+                format,
+                force,
+            } => {
+                let query_options = QueryOptions {
+                    bbox: *bbox,
+                    polygon: polygon.clone(),
+                    z_min: *z_min,
+                    z_max: *z_max,
+                    classes: class.clone(),
+                    predicates: filter
+                        .as_deref()
+                        .map(parse_predicates)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    output: output.clone(),
+                    format: *format,
+                    overwrite: *force,
+                };
+                query(input, &query_options)?;
+                Ok(())
+            }
+            #[cfg(feature = "parquet")]
+            ProcessType::Read { input, bbox } => {
+                read(input, *bbox)?;
+                Ok(())
+            }
             #[cfg(feature = "parquet")]
-            ProcessType::Read { input } => {
-                read(input);
+            ProcessType::Stats { input, json } => {
+                stats(input, *json)?;
                 Ok(())
             }
             ProcessType::Hello => {
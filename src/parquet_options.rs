@@ -0,0 +1,323 @@
+// knobs for how the parquet file itself gets written (compression, encodings, statistics),
+// mostly a trade between file size for archiving and read speed for streaming
+use std::fmt;
+use std::str::FromStr;
+
+use arrow_schema::{DataType, FieldRef, Schema};
+use parquet::basic::{BrotliLevel, Compression, Encoding, GzipLevel, ZstdLevel};
+use parquet::file::properties::{EnabledStatistics, WriterProperties, WriterPropertiesBuilder};
+use parquet::schema::types::ColumnPath;
+
+use crate::error::{QuafferError, Result};
+
+// codec plus optional level, e.g. "zstd", "zstd:9", "gzip:6", "lz4", "none"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionChoice(pub Compression);
+
+impl Default for CompressionChoice {
+    fn default() -> Self {
+        CompressionChoice(Compression::SNAPPY)
+    }
+}
+
+impl FromStr for CompressionChoice {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let lowered = s.trim().to_ascii_lowercase();
+        let (codec, level) = match lowered.split_once(':') {
+            Some((codec, level)) => {
+                let level = level.parse::<i32>().map_err(|err| {
+                    QuafferError::InvalidInput(format!("bad compression level in `{s}`: {err}"))
+                })?;
+                (codec, Some(level))
+            }
+            None => (lowered.as_str(), None),
+        };
+        let bad_level = |err: parquet::errors::ParquetError| {
+            QuafferError::InvalidInput(format!("bad compression level in `{s}`: {err}"))
+        };
+        let compression = match (codec, level) {
+            ("none" | "uncompressed", None) => Compression::UNCOMPRESSED,
+            ("snappy", None) => Compression::SNAPPY,
+            // the raw flavor, the older framed LZ4 isn't readable everywhere
+            ("lz4", None) => Compression::LZ4_RAW,
+            ("zstd", level) => Compression::ZSTD(match level {
+                Some(level) => ZstdLevel::try_new(level).map_err(bad_level)?,
+                None => ZstdLevel::default(),
+            }),
+            ("gzip", level) => Compression::GZIP(match level {
+                Some(level) => GzipLevel::try_new(level as u32).map_err(bad_level)?,
+                None => GzipLevel::default(),
+            }),
+            ("brotli", level) => Compression::BROTLI(match level {
+                Some(level) => BrotliLevel::try_new(level as u32).map_err(bad_level)?,
+                None => BrotliLevel::default(),
+            }),
+            _ => {
+                return Err(QuafferError::InvalidInput(format!(
+                    "unknown compression `{s}`, expected one of: none, snappy, lz4, \
+                    zstd[:level], gzip[:level], brotli[:level]"
+                )));
+            }
+        };
+        Ok(CompressionChoice(compression))
+    }
+}
+
+impl fmt::Display for CompressionChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Compression::UNCOMPRESSED => write!(f, "none"),
+            Compression::SNAPPY => write!(f, "snappy"),
+            Compression::LZ4_RAW | Compression::LZ4 => write!(f, "lz4"),
+            Compression::ZSTD(level) => write!(f, "zstd:{}", level.compression_level()),
+            Compression::GZIP(level) => write!(f, "gzip:{}", level.compression_level()),
+            Compression::BROTLI(level) => write!(f, "brotli:{}", level.compression_level()),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+// an encoding for one column, e.g. "fid=delta_binary_packed" or "xyz.x=byte_stream_split"
+// (nested columns like the point coords are dotted paths)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnEncoding {
+    pub column: String,
+    pub encoding: Encoding,
+}
+
+impl FromStr for ColumnEncoding {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (column, encoding) = s.split_once('=').ok_or_else(|| {
+            QuafferError::InvalidInput(format!(
+                "column encoding `{s}` should look like COLUMN=ENCODING"
+            ))
+        })?;
+        let encoding = match encoding.trim().to_ascii_lowercase().as_str() {
+            "plain" => Encoding::PLAIN,
+            "rle" => Encoding::RLE,
+            "delta_binary_packed" => Encoding::DELTA_BINARY_PACKED,
+            "delta_length_byte_array" => Encoding::DELTA_LENGTH_BYTE_ARRAY,
+            "delta_byte_array" => Encoding::DELTA_BYTE_ARRAY,
+            "byte_stream_split" => Encoding::BYTE_STREAM_SPLIT,
+            other => {
+                return Err(QuafferError::InvalidInput(format!(
+                    "unknown encoding `{other}`, expected one of: plain, rle, \
+                    delta_binary_packed, delta_length_byte_array, delta_byte_array, \
+                    byte_stream_split (dictionary encoding is on/off with --no-dictionary)"
+                )));
+            }
+        };
+        Ok(ColumnEncoding {
+            column: column.trim().to_string(),
+            encoding,
+        })
+    }
+}
+
+// how point geometries go into the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GeometryEncoding {
+    // GeoArrow points as a struct of x/y/z columns (GeoParquet 1.1 "point")
+    #[default]
+    Separated,
+    // GeoArrow points as a FixedSizeList of xyz values. opt-in only: GeoParquet's native
+    // "point" encoding means the separated struct, so readers other than ours may not
+    // pick these up
+    Interleaved,
+    // well-known binary, what GeoParquet 1.0 readers expect
+    Wkb,
+}
+
+impl GeometryEncoding {
+    // value for the `encoding` field in the `geo` metadata. interleaved has no name of
+    // its own in the spec, it goes under "point" with the layout in the field's
+    // geoarrow extension type (which is all our own reader needs)
+    pub fn metadata_name(&self) -> &'static str {
+        match self {
+            GeometryEncoding::Separated | GeometryEncoding::Interleaved => "point",
+            GeometryEncoding::Wkb => "WKB",
+        }
+    }
+
+    // how a geometry column that's already been encoded for parquet is laid out
+    pub fn from_data_type(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Binary | DataType::LargeBinary | DataType::BinaryView => {
+                GeometryEncoding::Wkb
+            }
+            DataType::FixedSizeList(..) => GeometryEncoding::Interleaved,
+            _ => GeometryEncoding::Separated,
+        }
+    }
+}
+
+impl FromStr for GeometryEncoding {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "separated" => Ok(GeometryEncoding::Separated),
+            "interleaved" => Ok(GeometryEncoding::Interleaved),
+            "wkb" => Ok(GeometryEncoding::Wkb),
+            other => Err(QuafferError::InvalidInput(format!(
+                "unknown geometry encoding `{other}`, expected separated, interleaved or wkb"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for GeometryEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeometryEncoding::Separated => write!(f, "separated"),
+            GeometryEncoding::Interleaved => write!(f, "interleaved"),
+            GeometryEncoding::Wkb => write!(f, "wkb"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatisticsLevel {
+    None,
+    // min/max per row group
+    Chunk,
+    // min/max per page too (plus the page index), lets readers skip pages
+    #[default]
+    Page,
+}
+
+impl FromStr for StatisticsLevel {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(StatisticsLevel::None),
+            "chunk" => Ok(StatisticsLevel::Chunk),
+            "page" => Ok(StatisticsLevel::Page),
+            other => Err(QuafferError::InvalidInput(format!(
+                "unknown statistics level `{other}`, expected none, chunk or page"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for StatisticsLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatisticsLevel::None => write!(f, "none"),
+            StatisticsLevel::Chunk => write!(f, "chunk"),
+            StatisticsLevel::Page => write!(f, "page"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    pub compression: CompressionChoice,
+    // explicit per-column encodings, these win over the presets below
+    pub column_encodings: Vec<ColumnEncoding>,
+    // BYTE_STREAM_SPLIT every float column (coords, gps_time, scan angle...)
+    pub byte_stream_split: bool,
+    // DELTA_BINARY_PACKED the fid column, which is (close to) sequential.
+    // not gps_time: it's a double and DELTA_BINARY_PACKED only takes integers,
+    // BYTE_STREAM_SPLIT (above, or per column) is what suits it
+    pub delta_fids: bool,
+    pub dictionary: bool,
+    pub statistics: StatisticsLevel,
+    // rows per row group, None means one row group per batch
+    pub row_group_size: Option<usize>,
+}
+
+// dotted parquet paths of every float leaf column, points included
+fn float_columns(fields: &[FieldRef], prefix: &[String], paths: &mut Vec<Vec<String>>) {
+    for field in fields {
+        let mut path = prefix.to_vec();
+        path.push(field.name().clone());
+        match field.data_type() {
+            DataType::Float32 | DataType::Float64 => paths.push(path),
+            DataType::Struct(children) => float_columns(children, &path, paths),
+            _ => {}
+        }
+    }
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            compression: CompressionChoice::default(),
+            column_encodings: Vec::new(),
+            byte_stream_split: false,
+            delta_fids: false,
+            dictionary: true,
+            statistics: StatisticsLevel::default(),
+            row_group_size: None,
+        }
+    }
+}
+
+impl ParquetOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.row_group_size == Some(0) {
+            return Err(QuafferError::InvalidInput(
+                "row group size must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // writer properties for a file with `schema` (the schema that actually hits parquet,
+    // after geoparquet encoding), `batch_size` is the row group size if none was given
+    pub fn writer_properties(&self, schema: &Schema, batch_size: usize) -> WriterProperties {
+        let statistics = match self.statistics {
+            StatisticsLevel::None => EnabledStatistics::None,
+            StatisticsLevel::Chunk => EnabledStatistics::Chunk,
+            StatisticsLevel::Page => EnabledStatistics::Page,
+        };
+        let mut builder = WriterProperties::builder()
+            .set_compression(self.compression.0)
+            .set_dictionary_enabled(self.dictionary)
+            .set_statistics_enabled(statistics)
+            .set_max_row_group_size(self.row_group_size.unwrap_or(batch_size));
+
+        let mut encodings: Vec<(ColumnPath, Encoding)> = Vec::new();
+        if self.byte_stream_split {
+            let mut paths = Vec::new();
+            float_columns(schema.fields(), &[], &mut paths);
+            encodings.extend(
+                paths
+                    .into_iter()
+                    .map(|path| (ColumnPath::new(path), Encoding::BYTE_STREAM_SPLIT)),
+            );
+        }
+        if self.delta_fids {
+            encodings.push((ColumnPath::from("fid"), Encoding::DELTA_BINARY_PACKED));
+        }
+        encodings.extend(self.column_encodings.iter().map(|column_encoding| {
+            let path = column_encoding
+                .column
+                .split('.')
+                .map(str::to_string)
+                .collect();
+            (ColumnPath::new(path), column_encoding.encoding)
+        }));
+        for (path, encoding) in encodings {
+            builder = with_column_encoding(builder, path, encoding);
+        }
+        builder.build()
+    }
+}
+
+fn with_column_encoding(
+    builder: WriterPropertiesBuilder,
+    path: ColumnPath,
+    encoding: Encoding,
+) -> WriterPropertiesBuilder {
+    // a dictionary page would take priority over the encoding we asked for
+    builder
+        .set_column_dictionary_enabled(path.clone(), false)
+        .set_column_encoding(path, encoding)
+}
@@ -0,0 +1,325 @@
+// PLY point clouds (photogrammetry and scanner exports, or our own `export --format ply`),
+// ascii or binary. only the vertex element is read, faces and the like are ignored
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use gdal::spatial_ref::SpatialRef;
+use las::Point;
+
+use crate::error::{QuafferError, Result};
+use crate::laz_to_gpq::{PointAttributes, WKTStringTransform};
+use crate::point_source::{PointField, PointSource, new_point, set_field};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyEncoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => PlyType::Char,
+            "uchar" | "uint8" => PlyType::UChar,
+            "short" | "int16" => PlyType::Short,
+            "ushort" | "uint16" => PlyType::UShort,
+            "int" | "int32" => PlyType::Int,
+            "uint" | "uint32" => PlyType::UInt,
+            "float" | "float32" => PlyType::Float,
+            "double" | "float64" => PlyType::Double,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::Char | PlyType::UChar => 1,
+            PlyType::Short | PlyType::UShort => 2,
+            PlyType::Int | PlyType::UInt | PlyType::Float => 4,
+            PlyType::Double => 8,
+        }
+    }
+
+    fn read(self, bytes: &[u8], encoding: PlyEncoding) -> f64 {
+        macro_rules! decode {
+            ($ty:ty) => {{
+                let bytes = bytes.try_into().expect("sliced to the property's size");
+                f64::from(match encoding {
+                    PlyEncoding::BigEndian => <$ty>::from_be_bytes(bytes),
+                    _ => <$ty>::from_le_bytes(bytes),
+                })
+            }};
+        }
+        match self {
+            PlyType::Char => decode!(i8),
+            PlyType::UChar => decode!(u8),
+            PlyType::Short => decode!(i16),
+            PlyType::UShort => decode!(u16),
+            PlyType::Int => decode!(i32),
+            PlyType::UInt => decode!(u32),
+            PlyType::Float => decode!(f32),
+            PlyType::Double => {
+                let bytes = bytes.try_into().expect("sliced to the property's size");
+                match encoding {
+                    PlyEncoding::BigEndian => f64::from_be_bytes(bytes),
+                    _ => f64::from_le_bytes(bytes),
+                }
+            }
+        }
+    }
+}
+
+struct PlyProperty {
+    field: PointField,
+    ply_type: PlyType,
+}
+
+impl PlyProperty {
+    // 8 bit colors get stretched to the 16 bits LAS uses
+    fn set(&self, point: &mut Point, value: f64) {
+        let value = match (self.field, self.ply_type) {
+            (PointField::Red | PointField::Green | PointField::Blue, PlyType::UChar) => {
+                value * 257.0
+            }
+            _ => value,
+        };
+        set_field(point, self.field, value);
+    }
+}
+
+pub struct PlySource {
+    path: PathBuf,
+    encoding: PlyEncoding,
+    properties: Vec<PlyProperty>,
+    vertex_count: u64,
+    // where the vertex data starts, just past `end_header`
+    data_offset: u64,
+    // from a `comment crs <definition>` line
+    crs: Option<String>,
+}
+
+impl PlySource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let name = path.display();
+        let bad_header = |msg: String| {
+            QuafferError::InvalidInput(format!("{name} isn't a PLY file we can read: {msg}"))
+        };
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut encoding = None;
+        let mut properties = Vec::new();
+        let mut vertex_count = None;
+        let mut crs = None;
+        // which element the `property` lines belong to
+        let mut in_vertex = false;
+        let mut data_offset: u64 = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                return Err(bad_header("no end_header line".to_string()));
+            }
+            data_offset += read as u64;
+            let trimmed = line.trim();
+            if data_offset == read as u64 {
+                if trimmed != "ply" {
+                    return Err(bad_header("doesn't start with `ply`".to_string()));
+                }
+                continue;
+            }
+            let mut tokens = trimmed.split_whitespace();
+            match tokens.next() {
+                Some("format") => {
+                    encoding = Some(match tokens.next() {
+                        Some("ascii") => PlyEncoding::Ascii,
+                        Some("binary_little_endian") => PlyEncoding::LittleEndian,
+                        Some("binary_big_endian") => PlyEncoding::BigEndian,
+                        other => {
+                            return Err(bad_header(format!("unknown format {other:?}")));
+                        }
+                    });
+                }
+                Some("comment") => {
+                    if let Some(definition) = trimmed
+                        .strip_prefix("comment")
+                        .map(str::trim)
+                        .and_then(|comment| comment.strip_prefix("crs "))
+                    {
+                        crs = Some(definition.trim().to_string());
+                    }
+                }
+                Some("element") => {
+                    let element = tokens.next();
+                    in_vertex = element == Some("vertex");
+                    if in_vertex {
+                        let count = tokens.next().and_then(|count| count.parse().ok());
+                        vertex_count = Some(count.ok_or_else(|| {
+                            bad_header("vertex element has no count".to_string())
+                        })?);
+                    } else if vertex_count.is_none() {
+                        // the vertex data has to come first for us to find it
+                        return Err(bad_header(format!(
+                            "`{}` element comes before the vertices",
+                            element.unwrap_or_default()
+                        )));
+                    }
+                }
+                Some("property") if in_vertex => {
+                    let type_name = tokens.next().unwrap_or_default();
+                    if type_name == "list" {
+                        return Err(bad_header("list properties on vertices".to_string()));
+                    }
+                    let ply_type = PlyType::parse(type_name).ok_or_else(|| {
+                        bad_header(format!("unknown property type `{type_name}`"))
+                    })?;
+                    let property_name = tokens.next().unwrap_or_default();
+                    let field = property_name.parse().unwrap_or_else(|_| {
+                        eprintln!("WARNING: {name}: ignoring vertex property `{property_name}`");
+                        PointField::Skip
+                    });
+                    properties.push(PlyProperty { field, ply_type });
+                }
+                Some("end_header") => break,
+                _ => {}
+            }
+        }
+        let encoding = encoding.ok_or_else(|| bad_header("no format line".to_string()))?;
+        let vertex_count =
+            vertex_count.ok_or_else(|| bad_header("no vertex element".to_string()))?;
+        for (required, label) in [(PointField::X, "x"), (PointField::Y, "y")] {
+            if !properties.iter().any(|property| property.field == required) {
+                return Err(bad_header(format!("vertices have no {label} property")));
+            }
+        }
+        Ok(PlySource {
+            path: path.to_path_buf(),
+            encoding,
+            properties,
+            vertex_count,
+            data_offset,
+            crs,
+        })
+    }
+
+    fn read_ascii(
+        &self,
+        reader: impl BufRead,
+        on_point: &mut dyn FnMut(Point) -> Result<bool>,
+    ) -> Result<()> {
+        let mut lines = reader.lines();
+        for vertex in 0..self.vertex_count {
+            let line = lines.next().transpose()?.ok_or_else(|| {
+                QuafferError::InvalidInput(format!(
+                    "{} ends after {vertex} of {} vertices",
+                    self.path.display(),
+                    self.vertex_count
+                ))
+            })?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() < self.properties.len() {
+                return Err(QuafferError::InvalidInput(format!(
+                    "{} vertex {vertex}: expected {} values, found {}",
+                    self.path.display(),
+                    self.properties.len(),
+                    tokens.len()
+                )));
+            }
+            let mut point = new_point();
+            for (property, token) in self.properties.iter().zip(tokens) {
+                if property.field == PointField::Skip {
+                    continue;
+                }
+                let value = token.parse::<f64>().map_err(|_| {
+                    QuafferError::InvalidInput(format!(
+                        "{} vertex {vertex}: `{token}` isn't a number",
+                        self.path.display()
+                    ))
+                })?;
+                property.set(&mut point, value);
+            }
+            if !on_point(point)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_binary(
+        &self,
+        mut reader: impl Read,
+        on_point: &mut dyn FnMut(Point) -> Result<bool>,
+    ) -> Result<()> {
+        let vertex_size = self
+            .properties
+            .iter()
+            .map(|property| property.ply_type.size())
+            .sum();
+        let mut vertex = vec![0u8; vertex_size];
+        for _ in 0..self.vertex_count {
+            reader.read_exact(&mut vertex)?;
+            let mut point = new_point();
+            let mut offset = 0;
+            for property in &self.properties {
+                let size = property.ply_type.size();
+                if property.field != PointField::Skip {
+                    let value = property
+                        .ply_type
+                        .read(&vertex[offset..offset + size], self.encoding);
+                    property.set(&mut point, value);
+                }
+                offset += size;
+            }
+            if !on_point(point)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PointSource for PlySource {
+    fn crs(&self) -> Result<Option<WKTStringTransform>> {
+        self.crs
+            .as_deref()
+            .map(|definition| {
+                WKTStringTransform::from_spatial_ref(&SpatialRef::from_definition(definition)?)
+            })
+            .transpose()
+    }
+
+    fn number_of_points(&self) -> u64 {
+        self.vertex_count
+    }
+
+    fn attributes(&self) -> PointAttributes {
+        let fields: Vec<PointField> = self
+            .properties
+            .iter()
+            .map(|property| property.field)
+            .collect();
+        PointField::attributes(&fields)
+    }
+
+    fn read_points(&mut self, on_point: &mut dyn FnMut(Point) -> Result<bool>) -> Result<()> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(self.data_offset))?;
+        match self.encoding {
+            PlyEncoding::Ascii => self.read_ascii(reader, on_point),
+            _ => self.read_binary(reader, on_point),
+        }
+    }
+}
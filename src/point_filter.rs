@@ -0,0 +1,235 @@
+// deciding which points make it out of the .laz file,
+// by ASPRS classification and by the withheld/overlap/synthetic flags
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use las::Point;
+
+use crate::error::QuafferError;
+
+// ASPRS standard classes (LAS 1.4 R15 table 17), plus a few common aliases
+const CLASS_NAMES: [(&str, u8); 21] = [
+    ("never_classified", 0),
+    ("created", 0),
+    ("unclassified", 1),
+    ("ground", 2),
+    ("low_vegetation", 3),
+    ("medium_vegetation", 4),
+    ("high_vegetation", 5),
+    ("building", 6),
+    ("low_point", 7),
+    ("noise", 7),
+    ("model_key_point", 8),
+    ("water", 9),
+    ("rail", 10),
+    ("road_surface", 11),
+    ("overlap", 12),
+    ("wire_guard", 13),
+    ("wire_conductor", 14),
+    ("transmission_tower", 15),
+    ("wire_structure_connector", 16),
+    ("bridge_deck", 17),
+    ("high_noise", 18),
+];
+
+// LAS < 1.4 doesn't have an overlap bit, overlap points get this class instead
+const LEGACY_OVERLAP_CLASS: u8 = 12;
+
+// canonical name for a class code, e.g. 2 -> "ground"
+// (LAS 1.4 reserves 19-63, 64 and up are user definable)
+pub fn class_label(code: u8) -> String {
+    match CLASS_NAMES.iter().find(|(_, class)| *class == code) {
+        Some((name, _)) => name.to_string(),
+        None if code < 64 => format!("reserved_{code}"),
+        None => format!("user_defined_{code}"),
+    }
+}
+
+// the other way around, for turning stored labels back into codes
+pub fn label_class_code(label: &str) -> Option<u8> {
+    if let Some(code) = label
+        .strip_prefix("reserved_")
+        .or_else(|| label.strip_prefix("user_defined_"))
+    {
+        return code.parse().ok();
+    }
+    CLASS_NAMES
+        .iter()
+        .find(|(name, _)| *name == label)
+        .map(|(_, code)| *code)
+}
+
+// a classification given either as its numeric code or its name ("2", "ground", "high-noise")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClassCode(pub u8);
+
+impl FromStr for ClassCode {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(code) = s.trim().parse::<u8>() {
+            return Ok(ClassCode(code));
+        }
+        let normalized = s.trim().to_ascii_lowercase().replace(['-', ' '], "_");
+        CLASS_NAMES
+            .iter()
+            .find(|(name, _)| *name == normalized)
+            .map(|(_, code)| ClassCode(*code))
+            .ok_or_else(|| {
+                QuafferError::InvalidInput(format!(
+                    "unknown classification `{s}`, use a code (0-255) or one of: {}",
+                    CLASS_NAMES
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+}
+
+// what to do with points that have a given flag set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlagFilter {
+    // flag doesn't matter
+    #[default]
+    Keep,
+    // throw away points with the flag set
+    Drop,
+    // only keep points with the flag set
+    Only,
+}
+
+impl FlagFilter {
+    fn allows(&self, flag_set: bool) -> bool {
+        match self {
+            FlagFilter::Keep => true,
+            FlagFilter::Drop => !flag_set,
+            FlagFilter::Only => flag_set,
+        }
+    }
+}
+
+impl FromStr for FlagFilter {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(FlagFilter::Keep),
+            "drop" => Ok(FlagFilter::Drop),
+            "only" => Ok(FlagFilter::Only),
+            other => Err(QuafferError::InvalidInput(format!(
+                "unknown flag filter `{other}`, expected keep, drop or only"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for FlagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagFilter::Keep => write!(f, "keep"),
+            FlagFilter::Drop => write!(f, "drop"),
+            FlagFilter::Only => write!(f, "only"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PointFilter {
+    // if set, only these classes get through
+    pub keep_classes: Option<HashSet<u8>>,
+    // these classes never get through (checked after keep_classes)
+    pub drop_classes: HashSet<u8>,
+    pub withheld: FlagFilter,
+    pub overlap: FlagFilter,
+    pub synthetic: FlagFilter,
+}
+
+impl PointFilter {
+    pub fn new(keep_classes: &[ClassCode], drop_classes: &[ClassCode]) -> Self {
+        PointFilter {
+            keep_classes: if keep_classes.is_empty() {
+                None
+            } else {
+                Some(keep_classes.iter().map(|class| class.0).collect())
+            },
+            drop_classes: drop_classes.iter().map(|class| class.0).collect(),
+            ..Default::default()
+        }
+    }
+
+    // whether this filter would ever throw a point away
+    pub fn is_active(&self) -> bool {
+        self.keep_classes.is_some()
+            || !self.drop_classes.is_empty()
+            || self.withheld != FlagFilter::Keep
+            || self.overlap != FlagFilter::Keep
+            || self.synthetic != FlagFilter::Keep
+    }
+
+    pub fn keep(&self, pnt: &Point) -> bool {
+        let class = u8::from(pnt.classification);
+        if let Some(keep_classes) = &self.keep_classes
+            && !keep_classes.contains(&class)
+        {
+            return false;
+        }
+        !self.drop_classes.contains(&class)
+            && self.withheld.allows(pnt.is_withheld)
+            && self
+                .overlap
+                .allows(pnt.is_overlap || class == LEGACY_OVERLAP_CLASS)
+            && self.synthetic.allows(pnt.is_synthetic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::point::Classification;
+
+    #[test]
+    fn class_codes_parse_from_codes_and_names() {
+        for (text, code) in [
+            ("2", 2),
+            (" 255 ", 255),
+            ("ground", 2),
+            ("Ground", 2),
+            ("high-noise", 18),
+            ("low vegetation", 3),
+            ("noise", 7),
+            ("created", 0),
+        ] {
+            assert_eq!(
+                text.parse::<ClassCode>().unwrap(),
+                ClassCode(code),
+                "{text}"
+            );
+        }
+        for bad in ["256", "-1", "gound", ""] {
+            assert!(bad.parse::<ClassCode>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn filter_checks_classes_and_flags() {
+        let point = |class: u8, is_withheld: bool| Point {
+            classification: Classification::new(class).unwrap(),
+            is_withheld,
+            ..Default::default()
+        };
+        let filter = PointFilter {
+            withheld: FlagFilter::Drop,
+            ..PointFilter::new(&[ClassCode(2), ClassCode(6)], &[ClassCode(6)])
+        };
+        assert!(filter.is_active());
+        assert!(filter.keep(&point(2, false)));
+        assert!(!filter.keep(&point(2, true)));
+        // drop wins over keep
+        assert!(!filter.keep(&point(6, false)));
+        assert!(!filter.keep(&point(9, false)));
+        assert!(!PointFilter::default().is_active());
+    }
+}
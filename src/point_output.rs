@@ -0,0 +1,891 @@
+// writing streamed point batches (from a geoparquet file) out to another file or stdout,
+// one batch at a time so nothing has to fit in memory
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Float32Type, Float64Type, Int8Type, Int16Type, Int32Type, Int64Type, UInt8Type, UInt16Type,
+    UInt32Type, UInt64Type,
+};
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::{DataType, Schema, SchemaRef};
+use flatgeobuf::{ColumnType, FgbCrs, FgbWriter, FgbWriterOptions, GeometryType};
+use geoparquet::writer::{
+    GeoParquetRecordBatchEncoder, GeoParquetWriterEncoding, GeoParquetWriterOptionsBuilder,
+};
+use geozero::{ColumnValue, FeatureProcessor, GeomProcessor, PropertyProcessor};
+use parquet::arrow::ArrowWriter;
+use parquet::file::metadata::{KeyValue, ParquetMetaData};
+use serde_json::{Value, json};
+
+use crate::bbox::Bbox;
+use crate::error::{QuafferError, Result};
+use crate::footer_keys::{BBOX_COLUMN, FOOTER_KEY_PREFIX};
+use crate::gpq_to_laz::crs_wkt;
+use crate::laz_to_gpq::{
+    DEFAULT_BATCH_SIZE, PartialFile, decode_class_codes, extend_bounds, finish_geo_metadata,
+};
+use crate::parquet_options::{GeometryEncoding, ParquetOptions};
+use crate::read_parq::Coords;
+
+// RFC 8142 record separator, starts every GeoJSON text sequence record
+const RECORD_SEPARATOR: char = '\u{1e}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    GeoParquet,
+    Csv,
+    GeoJson,
+    GeoJsonSeq,
+    FlatGeobuf,
+    Ply,
+}
+
+impl OutputFormat {
+    // from a file extension, None if it's not one we know
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "parquet" | "geoparquet" => Ok(OutputFormat::GeoParquet),
+            "csv" => Ok(OutputFormat::Csv),
+            "geojson" | "json" => Ok(OutputFormat::GeoJson),
+            "geojsonseq" | "geojsons" | "geojsonl" => Ok(OutputFormat::GeoJsonSeq),
+            "flatgeobuf" | "fgb" => Ok(OutputFormat::FlatGeobuf),
+            "ply" => Ok(OutputFormat::Ply),
+            other => Err(QuafferError::InvalidInput(format!(
+                "unknown output format `{other}`, expected parquet, csv, geojson, \
+                 geojsonseq, fgb or ply"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::GeoParquet => write!(f, "parquet"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::GeoJson => write!(f, "geojson"),
+            OutputFormat::GeoJsonSeq => write!(f, "geojsonseq"),
+            OutputFormat::FlatGeobuf => write!(f, "fgb"),
+            OutputFormat::Ply => write!(f, "ply"),
+        }
+    }
+}
+
+// --format if given, else from the output's extension, CSV for stdout
+pub fn pick_format(format: Option<OutputFormat>, path: Option<&Path>) -> Result<OutputFormat> {
+    match (format, path) {
+        (Some(format), _) => Ok(format),
+        (None, Some(path)) => OutputFormat::from_path(path).ok_or_else(|| {
+            QuafferError::InvalidInput(format!(
+                "can't tell the output format from {}, pass --format",
+                path.display()
+            ))
+        }),
+        (None, None) => Ok(OutputFormat::Csv),
+    }
+}
+
+// the source's point_quaffer: footer entries, carried over into geoparquet outputs (LAS headers, clip, extra bytes types...)
+pub fn carried_footer(metadata: &ParquetMetaData) -> Vec<KeyValue> {
+    metadata
+        .file_metadata()
+        .key_value_metadata()
+        .into_iter()
+        .flatten()
+        .filter(|key_value| key_value.key.starts_with(FOOTER_KEY_PREFIX))
+        .cloned()
+        .collect()
+}
+
+// the points' CRS in the shapes the output formats want it
+#[derive(Debug, Clone, Default)]
+pub struct OutputCrs {
+    // e.g. ("EPSG", 26915), None for CRSs without an authority code (compound ones, often)
+    pub authority: Option<(String, i32)>,
+    pub wkt: String,
+}
+
+impl OutputCrs {
+    // from the PROJJSON in the geoparquet metadata
+    pub fn from_projjson(projjson: &Value) -> Result<Self> {
+        let authority = projjson["id"]["authority"].as_str().and_then(|authority| {
+            let code = &projjson["id"]["code"];
+            let code = code
+                .as_i64()
+                .or_else(|| code.as_str().and_then(|code| code.parse().ok()))?;
+            Some((authority.to_string(), i32::try_from(code).ok()?))
+        });
+        let (wkt, _) = crs_wkt(&projjson.to_string())?;
+        Ok(OutputCrs { authority, wkt })
+    }
+
+    // "EPSG:26915", or the WKT without line breaks
+    fn label(&self) -> String {
+        match &self.authority {
+            Some((authority, code)) => format!("{authority}:{code}"),
+            None => self.wkt.replace(['\n', '\r'], " "),
+        }
+    }
+}
+
+pub trait PointSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+// a file (written to a partial file first) or stdout
+struct Output {
+    out: Box<dyn Write>,
+    partial_file: Option<PartialFile>,
+}
+
+impl Output {
+    fn open(path: Option<&Path>) -> Result<Self> {
+        Ok(match path {
+            Some(path) => {
+                let partial_file = PartialFile::new(path);
+                Output {
+                    out: Box::new(BufWriter::new(File::create(&partial_file.path)?)),
+                    partial_file: Some(partial_file),
+                }
+            }
+            None => Output {
+                out: Box::new(BufWriter::new(std::io::stdout().lock())),
+                partial_file: None,
+            },
+        })
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.out.flush()?;
+        drop(self.out);
+        if let Some(partial_file) = self.partial_file {
+            partial_file.persist()?;
+        }
+        Ok(())
+    }
+}
+
+// whether the point geometry carries z itself
+fn has_z(schema: &Schema, geometry_column: &str) -> bool {
+    match schema
+        .field_with_name(geometry_column)
+        .map(|field| field.data_type())
+    {
+        Ok(DataType::Struct(fields)) => fields.len() > 2,
+        Ok(DataType::FixedSizeList(_, size)) => *size > 2,
+        _ => false,
+    }
+}
+
+// (index, name) of every column that isn't the geometry (or the bbox covering column,
+// which just repeats it)
+fn attribute_columns(schema: &Schema, geometry_column: &str) -> Vec<(usize, String)> {
+    schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.name() != geometry_column && field.name() != BBOX_COLUMN)
+        .map(|(i, field)| (i, field.name().clone()))
+        .collect()
+}
+
+fn formatters<'a>(
+    batch: &'a RecordBatch,
+    columns: &[(usize, String)],
+    options: &FormatOptions<'a>,
+) -> Result<Vec<ArrayFormatter<'a>>> {
+    Ok(columns
+        .iter()
+        .map(|(i, _)| ArrayFormatter::try_new(batch.column(*i).as_ref(), options))
+        .collect::<std::result::Result<Vec<_>, _>>()?)
+}
+
+fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+struct CsvSink {
+    output: Output,
+    geometry_column: String,
+    columns: Vec<(usize, String)>,
+}
+
+impl CsvSink {
+    fn open(path: Option<&Path>, schema: &Schema, geometry_column: &str) -> Result<Self> {
+        let mut output = Output::open(path)?;
+        let columns = attribute_columns(schema, geometry_column);
+        let mut header = vec!["x", "y"];
+        if has_z(schema, geometry_column) {
+            header.push("z");
+        }
+        header.extend(columns.iter().map(|(_, name)| name.as_str()));
+        let header: Vec<Cow<str>> = header.into_iter().map(csv_field).collect();
+        writeln!(output.out, "{}", header.join(","))?;
+        Ok(CsvSink {
+            output,
+            geometry_column: geometry_column.to_string(),
+            columns,
+        })
+    }
+}
+
+impl PointSink for CsvSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let coords = Coords::from_batch(batch, &self.geometry_column)?;
+        let options = FormatOptions::default();
+        let formatters = formatters(batch, &self.columns, &options)?;
+        for idx in 0..batch.num_rows() {
+            let (x, y, z) = coords.xyz(idx);
+            let mut fields = vec![x.to_string(), y.to_string()];
+            fields.extend(z.map(|z| z.to_string()));
+            for formatter in &formatters {
+                fields.push(csv_field(&formatter.value(idx).to_string()).into_owned());
+            }
+            writeln!(self.output.out, "{}", fields.join(","))?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.output.finish()
+    }
+}
+
+// a property as JSON, numbers and bools stay numbers and bools
+fn property_value(array: &dyn Array, formatter: &ArrayFormatter, idx: usize) -> Value {
+    if array.is_null(idx) {
+        return Value::Null;
+    }
+    let text = formatter.value(idx).to_string();
+    let data_type = array.data_type();
+    if data_type.is_numeric() || data_type == &DataType::Boolean {
+        // NaN and friends aren't JSON numbers, they stay strings
+        serde_json::from_str(&text).unwrap_or(Value::String(text))
+    } else {
+        Value::String(text)
+    }
+}
+
+// a point and its attributes as a GeoJSON feature
+fn feature(
+    batch: &RecordBatch,
+    coords: &Coords,
+    columns: &[(usize, String)],
+    formatters: &[ArrayFormatter],
+    idx: usize,
+) -> Value {
+    let (x, y, z) = coords.xyz(idx);
+    let coordinates = match z {
+        Some(z) => json!([x, y, z]),
+        None => json!([x, y]),
+    };
+    let properties: serde_json::Map<String, Value> = columns
+        .iter()
+        .zip(formatters)
+        .map(|((i, name), formatter)| {
+            let value = property_value(batch.column(*i).as_ref(), formatter, idx);
+            (name.clone(), value)
+        })
+        .collect();
+    json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": coordinates },
+        "properties": properties,
+    })
+}
+
+// a FeatureCollection written a feature at a time, or a GeoJSON text sequence
+// (RFC 8142) with one feature per record
+struct GeoJsonSink {
+    output: Output,
+    geometry_column: String,
+    columns: Vec<(usize, String)>,
+    sequence: bool,
+    features_written: u64,
+}
+
+impl GeoJsonSink {
+    fn open(
+        path: Option<&Path>,
+        schema: &Schema,
+        geometry_column: &str,
+        crs: Option<&OutputCrs>,
+        sequence: bool,
+    ) -> Result<Self> {
+        let mut output = Output::open(path)?;
+        if !sequence {
+            // RFC 7946 dropped `crs`, but GDAL and QGIS still read the old
+            // named CRS member, and without it everyone assumes lon/lat
+            let crs = crs
+                .and_then(|crs| crs.authority.as_ref())
+                .map(|(authority, code)| {
+                    let name = format!("urn:ogc:def:crs:{authority}::{code}");
+                    format!(
+                        "\"crs\":{},",
+                        json!({"type": "name", "properties": {"name": name}})
+                    )
+                })
+                .unwrap_or_default();
+            write!(
+                output.out,
+                "{{\"type\":\"FeatureCollection\",{crs}\"features\":["
+            )?;
+        }
+        Ok(GeoJsonSink {
+            output,
+            geometry_column: geometry_column.to_string(),
+            columns: attribute_columns(schema, geometry_column),
+            sequence,
+            features_written: 0,
+        })
+    }
+}
+
+impl PointSink for GeoJsonSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let coords = Coords::from_batch(batch, &self.geometry_column)?;
+        let options = FormatOptions::default();
+        let formatters = formatters(batch, &self.columns, &options)?;
+        for idx in 0..batch.num_rows() {
+            let feature = feature(batch, &coords, &self.columns, &formatters, idx);
+            if self.sequence {
+                writeln!(self.output.out, "{RECORD_SEPARATOR}{feature}")?;
+            } else {
+                let separator = if self.features_written == 0 {
+                    "\n"
+                } else {
+                    ",\n"
+                };
+                write!(self.output.out, "{separator}{feature}")?;
+            }
+            self.features_written += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if !self.sequence {
+            writeln!(self.output.out, "\n]}}")?;
+        }
+        self.output.finish()
+    }
+}
+
+// column types FlatGeobuf and PLY can hold as they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Byte,
+    UByte,
+    Bool,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Float,
+    Double,
+    Text,
+}
+
+impl ValueKind {
+    fn of(data_type: &DataType) -> Option<Self> {
+        Some(match data_type {
+            DataType::Int8 => ValueKind::Byte,
+            DataType::UInt8 => ValueKind::UByte,
+            DataType::Boolean => ValueKind::Bool,
+            DataType::Int16 => ValueKind::Short,
+            DataType::UInt16 => ValueKind::UShort,
+            DataType::Int32 => ValueKind::Int,
+            DataType::UInt32 => ValueKind::UInt,
+            DataType::Int64 => ValueKind::Long,
+            DataType::UInt64 => ValueKind::ULong,
+            DataType::Float32 => ValueKind::Float,
+            DataType::Float64 => ValueKind::Double,
+            DataType::Utf8 => ValueKind::Text,
+            // classification, written as class codes (see `plain_columns`)
+            DataType::Dictionary(key, _) if **key == DataType::UInt8 => ValueKind::UByte,
+            _ => return None,
+        })
+    }
+
+    fn fgb_type(self) -> ColumnType {
+        match self {
+            ValueKind::Byte => ColumnType::Byte,
+            ValueKind::UByte => ColumnType::UByte,
+            ValueKind::Bool => ColumnType::Bool,
+            ValueKind::Short => ColumnType::Short,
+            ValueKind::UShort => ColumnType::UShort,
+            ValueKind::Int => ColumnType::Int,
+            ValueKind::UInt => ColumnType::UInt,
+            ValueKind::Long => ColumnType::Long,
+            ValueKind::ULong => ColumnType::ULong,
+            ValueKind::Float => ColumnType::Float,
+            ValueKind::Double => ColumnType::Double,
+            ValueKind::Text => ColumnType::String,
+        }
+    }
+
+    // PLY has no 64 bit integers or strings, the big ints go out as doubles
+    fn ply_type(self) -> Option<&'static str> {
+        Some(match self {
+            ValueKind::Byte => "char",
+            ValueKind::UByte | ValueKind::Bool => "uchar",
+            ValueKind::Short => "short",
+            ValueKind::UShort => "ushort",
+            ValueKind::Int => "int",
+            ValueKind::UInt => "uint",
+            ValueKind::Float => "float",
+            ValueKind::Long | ValueKind::ULong | ValueKind::Double => "double",
+            ValueKind::Text => return None,
+        })
+    }
+
+    fn ply_size(self) -> usize {
+        match self {
+            ValueKind::Byte | ValueKind::UByte | ValueKind::Bool => 1,
+            ValueKind::Short | ValueKind::UShort => 2,
+            ValueKind::Int | ValueKind::UInt | ValueKind::Float => 4,
+            ValueKind::Long | ValueKind::ULong | ValueKind::Double | ValueKind::Text => 8,
+        }
+    }
+}
+
+// (index, name, kind) of the attribute columns a format can hold, with a warning for the rest
+fn typed_columns(
+    schema: &Schema,
+    geometry_column: &str,
+    format: &str,
+    keep: impl Fn(ValueKind) -> bool,
+) -> Vec<(usize, String, ValueKind)> {
+    attribute_columns(schema, geometry_column)
+        .into_iter()
+        .filter_map(|(i, name)| {
+            match ValueKind::of(schema.field(i).data_type()).filter(|kind| keep(*kind)) {
+                Some(kind) => Some((i, name, kind)),
+                None => {
+                    eprintln!(
+                        "WARNING: {format} can't hold column `{name}` ({}), skipping it",
+                        schema.field(i).data_type()
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// a batch's attribute columns with the classification dictionary decoded to class codes
+// (the file's dictionary keys are just positions in its labels)
+fn plain_columns(
+    batch: &RecordBatch,
+    columns: &[(usize, String, ValueKind)],
+) -> Result<Vec<ArrayRef>> {
+    columns
+        .iter()
+        .map(|(i, _, _)| {
+            let column = batch.column(*i);
+            Ok(match column.as_dictionary_opt::<UInt8Type>() {
+                Some(dictionary) => Arc::new(decode_class_codes(dictionary)?) as ArrayRef,
+                None => column.clone(),
+            })
+        })
+        .collect()
+}
+
+// None for nulls
+fn column_value(array: &dyn Array, kind: ValueKind, idx: usize) -> Option<ColumnValue<'_>> {
+    if array.is_null(idx) {
+        return None;
+    }
+    Some(match kind {
+        ValueKind::Byte => ColumnValue::Byte(array.as_primitive::<Int8Type>().value(idx)),
+        ValueKind::UByte => ColumnValue::UByte(array.as_primitive::<UInt8Type>().value(idx)),
+        ValueKind::Bool => ColumnValue::Bool(array.as_boolean().value(idx)),
+        ValueKind::Short => ColumnValue::Short(array.as_primitive::<Int16Type>().value(idx)),
+        ValueKind::UShort => ColumnValue::UShort(array.as_primitive::<UInt16Type>().value(idx)),
+        ValueKind::Int => ColumnValue::Int(array.as_primitive::<Int32Type>().value(idx)),
+        ValueKind::UInt => ColumnValue::UInt(array.as_primitive::<UInt32Type>().value(idx)),
+        ValueKind::Long => ColumnValue::Long(array.as_primitive::<Int64Type>().value(idx)),
+        ValueKind::ULong => ColumnValue::ULong(array.as_primitive::<UInt64Type>().value(idx)),
+        ValueKind::Float => ColumnValue::Float(array.as_primitive::<Float32Type>().value(idx)),
+        ValueKind::Double => ColumnValue::Double(array.as_primitive::<Float64Type>().value(idx)),
+        ValueKind::Text => ColumnValue::String(array.as_string::<i32>().value(idx)),
+    })
+}
+
+fn fgb_error(err: impl fmt::Display) -> QuafferError {
+    QuafferError::InvalidInput(format!("couldn't write FlatGeobuf: {err}"))
+}
+
+// FlatGeobuf with a packed Hilbert R-tree index. the writer spools features to a temp
+// file and sorts them into index order once they're all in, so memory stays flat
+struct FlatGeobufSink {
+    output: Output,
+    writer: FgbWriter<'static>,
+    geometry_column: String,
+    columns: Vec<(usize, String, ValueKind)>,
+    features_written: u64,
+}
+
+impl FlatGeobufSink {
+    fn open(
+        path: Option<&Path>,
+        schema: &Schema,
+        geometry_column: &str,
+        crs: Option<&OutputCrs>,
+    ) -> Result<Self> {
+        let name = path
+            .and_then(|path| path.file_stem())
+            .map_or("points".into(), |stem| stem.to_string_lossy());
+        let crs = match crs {
+            Some(crs) => FgbCrs {
+                org: crs
+                    .authority
+                    .as_ref()
+                    .map(|(authority, _)| authority.as_str()),
+                code: crs.authority.as_ref().map_or(0, |(_, code)| *code),
+                wkt: Some(crs.wkt.as_str()),
+                ..Default::default()
+            },
+            None => FgbCrs::default(),
+        };
+        let options = FgbWriterOptions {
+            write_index: true,
+            crs,
+            has_z: has_z(schema, geometry_column),
+            ..Default::default()
+        };
+        let mut writer = FgbWriter::create_with_options(&name, GeometryType::Point, options)
+            .map_err(fgb_error)?;
+        let columns = typed_columns(schema, geometry_column, "FlatGeobuf", |_| true);
+        for (_, name, kind) in &columns {
+            writer.add_column(name, kind.fgb_type(), |_, column| column.nullable = true);
+        }
+        Ok(FlatGeobufSink {
+            output: Output::open(path)?,
+            writer,
+            geometry_column: geometry_column.to_string(),
+            columns,
+            features_written: 0,
+        })
+    }
+}
+
+impl PointSink for FlatGeobufSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let coords = Coords::from_batch(batch, &self.geometry_column)?;
+        let arrays = plain_columns(batch, &self.columns)?;
+        for idx in 0..batch.num_rows() {
+            let (x, y, z) = coords.xyz(idx);
+            let feature = self.features_written;
+            self.writer.feature_begin(feature)?;
+            self.writer.properties_begin()?;
+            for (property, ((_, name, kind), array)) in self.columns.iter().zip(&arrays).enumerate()
+            {
+                // nulls are just left out of the feature
+                if let Some(value) = column_value(array.as_ref(), *kind, idx) {
+                    self.writer.property(property, name, &value)?;
+                }
+            }
+            self.writer.properties_end()?;
+            self.writer.geometry_begin()?;
+            self.writer.point_begin(0)?;
+            match z {
+                Some(z) => self.writer.coordinate(x, y, Some(z), None, None, None, 0)?,
+                None => self.writer.xy(x, y, 0)?,
+            }
+            self.writer.point_end(0)?;
+            self.writer.geometry_end()?;
+            self.writer.feature_end(feature)?;
+            self.features_written += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let FlatGeobufSink {
+            mut output, writer, ..
+        } = *self;
+        writer.write(&mut output.out).map_err(fgb_error)?;
+        output.finish()
+    }
+}
+
+fn write_ply_value(
+    out: &mut impl Write,
+    kind: ValueKind,
+    value: Option<ColumnValue>,
+) -> std::io::Result<()> {
+    match value {
+        Some(ColumnValue::Byte(value)) => out.write_all(&value.to_le_bytes()),
+        Some(ColumnValue::UByte(value)) => out.write_all(&[value]),
+        Some(ColumnValue::Bool(value)) => out.write_all(&[u8::from(value)]),
+        Some(ColumnValue::Short(value)) => out.write_all(&value.to_le_bytes()),
+        Some(ColumnValue::UShort(value)) => out.write_all(&value.to_le_bytes()),
+        Some(ColumnValue::Int(value)) => out.write_all(&value.to_le_bytes()),
+        Some(ColumnValue::UInt(value)) => out.write_all(&value.to_le_bytes()),
+        Some(ColumnValue::Long(value)) => out.write_all(&(value as f64).to_le_bytes()),
+        Some(ColumnValue::ULong(value)) => out.write_all(&(value as f64).to_le_bytes()),
+        Some(ColumnValue::Float(value)) => out.write_all(&value.to_le_bytes()),
+        Some(ColumnValue::Double(value)) => out.write_all(&value.to_le_bytes()),
+        // nulls (PLY has no such thing) go out as zero
+        _ => out.write_all(&[0; 8][..kind.ply_size()]),
+    }
+}
+
+// binary little endian PLY. the header needs the vertex count up front, so the
+// vertices get spooled to a temp file and copied in behind the header at the end
+struct PlySink {
+    output: Output,
+    vertices: BufWriter<File>,
+    vertices_file: PartialFile,
+    geometry_column: String,
+    has_z: bool,
+    columns: Vec<(usize, String, ValueKind)>,
+    crs: Option<String>,
+    vertex_count: u64,
+}
+
+impl PlySink {
+    fn open(
+        path: Option<&Path>,
+        schema: &Schema,
+        geometry_column: &str,
+        crs: Option<&OutputCrs>,
+    ) -> Result<Self> {
+        // never persisted, it's gone once the sink is
+        let vertices_file = PartialFile::new(&match path {
+            Some(path) => path.with_extension("ply-vertices"),
+            None => std::env::temp_dir().join("point-quaffer.ply-vertices"),
+        });
+        Ok(PlySink {
+            output: Output::open(path)?,
+            vertices: BufWriter::new(File::create(&vertices_file.path)?),
+            vertices_file,
+            geometry_column: geometry_column.to_string(),
+            has_z: has_z(schema, geometry_column),
+            columns: typed_columns(schema, geometry_column, "PLY", |kind| {
+                kind.ply_type().is_some()
+            }),
+            crs: crs.map(OutputCrs::label),
+            vertex_count: 0,
+        })
+    }
+}
+
+impl PointSink for PlySink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let coords = Coords::from_batch(batch, &self.geometry_column)?;
+        let arrays = plain_columns(batch, &self.columns)?;
+        for idx in 0..batch.num_rows() {
+            let (x, y, z) = coords.xyz(idx);
+            self.vertices.write_all(&x.to_le_bytes())?;
+            self.vertices.write_all(&y.to_le_bytes())?;
+            if self.has_z {
+                self.vertices
+                    .write_all(&z.unwrap_or_default().to_le_bytes())?;
+            }
+            for ((_, _, kind), array) in self.columns.iter().zip(&arrays) {
+                let value = column_value(array.as_ref(), *kind, idx);
+                write_ply_value(&mut self.vertices, *kind, value)?;
+            }
+            self.vertex_count += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let PlySink {
+            mut output,
+            vertices,
+            vertices_file,
+            has_z,
+            columns,
+            crs,
+            vertex_count,
+            ..
+        } = *self;
+        vertices.into_inner().map_err(|err| err.into_error())?;
+        let out = &mut output.out;
+        writeln!(out, "ply")?;
+        writeln!(out, "format binary_little_endian 1.0")?;
+        writeln!(out, "comment generated by point-quaffer")?;
+        if let Some(crs) = crs {
+            writeln!(out, "comment crs {crs}")?;
+        }
+        writeln!(out, "element vertex {vertex_count}")?;
+        let coords = if has_z {
+            ["x", "y", "z"].as_slice()
+        } else {
+            &["x", "y"]
+        };
+        for coord in coords {
+            writeln!(out, "property double {coord}")?;
+        }
+        for (_, name, kind) in &columns {
+            if let Some(ply_type) = kind.ply_type() {
+                writeln!(out, "property {ply_type} {name}")?;
+            }
+        }
+        writeln!(out, "end_header")?;
+        std::io::copy(&mut File::open(&vertices_file.path)?, out)?;
+        output.finish()
+    }
+}
+
+struct GeoParquetSink {
+    encoder: GeoParquetRecordBatchEncoder,
+    writer: ArrowWriter<File>,
+    partial_file: PartialFile,
+    footer: Vec<KeyValue>,
+    geometry_column: String,
+    geometry_encoding: GeometryEncoding,
+    // the source's bbox covering column comes along, so the footer has to say so
+    bbox_column: bool,
+    // bounds of the rows written, not the source file's
+    bounds: Option<Bbox>,
+}
+
+impl GeoParquetSink {
+    fn open(
+        path: &Path,
+        schema: &SchemaRef,
+        geometry_column: &str,
+        footer: Vec<KeyValue>,
+    ) -> Result<Self> {
+        // the CRS rides along in the geometry field's metadata
+        let options = GeoParquetWriterOptionsBuilder::default()
+            .set_primary_column(geometry_column.to_string())
+            .set_encoding(GeoParquetWriterEncoding::GeoArrow)
+            .build();
+        let encoder = GeoParquetRecordBatchEncoder::try_new(schema, &options)?;
+        let target_schema = encoder.target_schema();
+        let geometry_encoding = target_schema
+            .field_with_name(geometry_column)
+            .map(|field| GeometryEncoding::from_data_type(field.data_type()))
+            .unwrap_or_default();
+        let bbox_column = target_schema.field_with_name(BBOX_COLUMN).is_ok();
+        let partial_file = PartialFile::new(path);
+        let props =
+            ParquetOptions::default().writer_properties(target_schema.as_ref(), DEFAULT_BATCH_SIZE);
+        let writer = ArrowWriter::try_new(
+            File::create(&partial_file.path)?,
+            target_schema,
+            Some(props),
+        )?;
+        Ok(GeoParquetSink {
+            encoder,
+            writer,
+            partial_file,
+            footer,
+            geometry_column: geometry_column.to_string(),
+            geometry_encoding,
+            bbox_column,
+            bounds: None,
+        })
+    }
+}
+
+impl PointSink for GeoParquetSink {
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.bounds = extend_bounds(self.bounds, batch);
+        let encoded = self.encoder.encode_record_batch(batch)?;
+        self.writer.write(&encoded)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let GeoParquetSink {
+            encoder,
+            mut writer,
+            partial_file,
+            footer,
+            geometry_column,
+            geometry_encoding,
+            bbox_column,
+            bounds,
+        } = *self;
+        writer.append_key_value_metadata(finish_geo_metadata(
+            encoder.into_keyvalue()?,
+            &geometry_column,
+            geometry_encoding,
+            bbox_column,
+            bounds,
+        )?);
+        for key_value in footer {
+            writer.append_key_value_metadata(key_value);
+        }
+        writer.close()?;
+        partial_file.persist()
+    }
+}
+
+// `path` None means stdout (everything but geoparquet), `footer` only goes into
+// geoparquet outputs, which get their CRS from the geometry field metadata instead of `crs`
+pub fn open_sink(
+    format: OutputFormat,
+    path: Option<&Path>,
+    schema: &SchemaRef,
+    geometry_column: &str,
+    crs: Option<&OutputCrs>,
+    footer: Vec<KeyValue>,
+    overwrite: bool,
+) -> Result<Box<dyn PointSink>> {
+    if let Some(path) = path
+        && !overwrite
+        && path.exists()
+    {
+        return Err(QuafferError::InvalidInput(format!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        )));
+    }
+    Ok(match (format, path) {
+        (OutputFormat::Csv, path) => Box::new(CsvSink::open(path, schema, geometry_column)?),
+        (OutputFormat::GeoJson, path) => Box::new(GeoJsonSink::open(
+            path,
+            schema,
+            geometry_column,
+            crs,
+            false,
+        )?),
+        (OutputFormat::GeoJsonSeq, path) => {
+            Box::new(GeoJsonSink::open(path, schema, geometry_column, crs, true)?)
+        }
+        (OutputFormat::FlatGeobuf, path) => {
+            Box::new(FlatGeobufSink::open(path, schema, geometry_column, crs)?)
+        }
+        (OutputFormat::Ply, path) => Box::new(PlySink::open(path, schema, geometry_column, crs)?),
+        (OutputFormat::GeoParquet, Some(path)) => {
+            Box::new(GeoParquetSink::open(path, schema, geometry_column, footer)?)
+        }
+        (OutputFormat::GeoParquet, None) => {
+            return Err(QuafferError::InvalidInput(
+                "geoparquet output needs a file, pass --output".to_string(),
+            ));
+        }
+    })
+}
@@ -0,0 +1,279 @@
+// the point cloud formats laz-import can read: LAS/LAZ, PLY, XYZ/CSV and E57.
+// each one hands out plain `las::Point`s, so filtering, sampling, sorting and
+// writing don't care where the points came from
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+use las::point::Classification;
+use las::{Color, LazParallelism, Point, Reader, ReaderOptions};
+use serde_json::Value;
+
+use crate::e57_source::E57Source;
+use crate::error::{QuafferError, Result};
+use crate::extra_bytes::ExtraBytesDescriptor;
+use crate::las_header::header_json;
+use crate::laz_to_gpq::{PointAttributes, WKTStringTransform, crs_from_header};
+use crate::ply_source::PlySource;
+use crate::xyz_source::{CsvOptions, XyzSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Las,
+    Ply,
+    // delimited text: XYZ, CSV, TXT, PTS
+    Csv,
+    E57,
+}
+
+impl SourceFormat {
+    // from a file extension, None if it's not a point cloud we can read
+    // (.txt is too common to guess at, it needs --input-format csv)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match extension.as_str() {
+            "las" | "laz" => Some(SourceFormat::Las),
+            "ply" => Some(SourceFormat::Ply),
+            "csv" | "xyz" | "pts" => Some(SourceFormat::Csv),
+            "e57" => Some(SourceFormat::E57),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SourceFormat {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "las" | "laz" => Ok(SourceFormat::Las),
+            "ply" => Ok(SourceFormat::Ply),
+            "csv" | "xyz" | "txt" | "pts" => Ok(SourceFormat::Csv),
+            "e57" => Ok(SourceFormat::E57),
+            other => Err(QuafferError::InvalidInput(format!(
+                "unknown input format `{other}`, expected las, ply, csv or e57"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SourceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceFormat::Las => write!(f, "las"),
+            SourceFormat::Ply => write!(f, "ply"),
+            SourceFormat::Csv => write!(f, "csv"),
+            SourceFormat::E57 => write!(f, "e57"),
+        }
+    }
+}
+
+// how to read the input files, on top of what they say about themselves
+#[derive(Debug, Clone, Default)]
+pub struct SourceOptions {
+    // None goes by each file's extension
+    pub format: Option<SourceFormat>,
+    // CRS of the input points (anything gdal's SpatialRef::from_definition takes),
+    // overrides whatever the file says, and the only way to give XYZ/CSV files one
+    pub crs: Option<String>,
+    pub csv: CsvOptions,
+}
+
+// LAS point fields a column of a text or PLY file can fill
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointField {
+    X,
+    Y,
+    Z,
+    Intensity,
+    ReturnNumber,
+    NumberOfReturns,
+    Classification,
+    ScanAngle,
+    UserData,
+    PointSourceId,
+    GpsTime,
+    Red,
+    Green,
+    Blue,
+    // a column that doesn't map onto anything
+    Skip,
+}
+
+impl PointField {
+    // the optional LAS attributes these fields need columns for
+    pub fn attributes(fields: &[PointField]) -> PointAttributes {
+        PointAttributes {
+            gps_time: fields.contains(&PointField::GpsTime),
+            color: fields.iter().any(|field| {
+                matches!(
+                    field,
+                    PointField::Red | PointField::Green | PointField::Blue
+                )
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl FromStr for PointField {
+    type Err = QuafferError;
+
+    // our own column names, plus what surveying and photogrammetry tools tend to call them
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let normalized = s.trim().to_ascii_lowercase().replace(['-', ' '], "_");
+        // CloudCompare writes its scalar fields as `scalar_<name>`
+        let normalized = normalized.strip_prefix("scalar_").unwrap_or(&normalized);
+        Ok(match normalized {
+            "x" | "easting" | "east" | "e" => PointField::X,
+            "y" | "northing" | "north" | "n" => PointField::Y,
+            "z" | "elevation" | "elev" | "height" | "h" => PointField::Z,
+            "intensity" | "i" => PointField::Intensity,
+            "return_number" | "returnnumber" => PointField::ReturnNumber,
+            "number_of_returns" | "numberofreturns" => PointField::NumberOfReturns,
+            "classification" | "class" => PointField::Classification,
+            "scan_angle" | "scanangle" | "scan_angle_rank" => PointField::ScanAngle,
+            "user_data" | "userdata" => PointField::UserData,
+            "point_source_id" | "pointsourceid" => PointField::PointSourceId,
+            "gps_time" | "gpstime" | "time" => PointField::GpsTime,
+            "red" | "r" | "diffuse_red" => PointField::Red,
+            "green" | "g" | "diffuse_green" => PointField::Green,
+            "blue" | "b" | "diffuse_blue" => PointField::Blue,
+            "_" | "skip" | "ignore" => PointField::Skip,
+            _ => {
+                return Err(QuafferError::InvalidInput(format!(
+                    "unknown point field `{s}`, expected x, y, z, intensity, return_number, \
+                     number_of_returns, classification, scan_angle, user_data, \
+                     point_source_id, gps_time, red, green, blue or skip"
+                )));
+            }
+        })
+    }
+}
+
+// a single return, unclassified, until the file says otherwise
+pub fn new_point() -> Point {
+    Point {
+        return_number: 1,
+        number_of_returns: 1,
+        classification: Classification::Unclassified,
+        ..Default::default()
+    }
+}
+
+// values outside a field's LAS type get clamped, colors are 16 bit like LAS
+pub fn set_field(point: &mut Point, field: PointField, value: f64) {
+    match field {
+        PointField::X => point.x = value,
+        PointField::Y => point.y = value,
+        PointField::Z => point.z = value,
+        PointField::Intensity => point.intensity = value as u16,
+        PointField::ReturnNumber => point.return_number = value as u8,
+        PointField::NumberOfReturns => point.number_of_returns = value as u8,
+        PointField::Classification => {
+            // 12 is reserved for overlap in 1.4, las wants it in the overlap flag instead
+            point.classification = Classification::new(value as u8).unwrap_or_else(|_| {
+                point.is_overlap = true;
+                Classification::Unclassified
+            });
+        }
+        PointField::ScanAngle => point.scan_angle = value as f32,
+        PointField::UserData => point.user_data = value as u8,
+        PointField::PointSourceId => point.point_source_id = value as u16,
+        PointField::GpsTime => point.gps_time = Some(value),
+        PointField::Red | PointField::Green | PointField::Blue => {
+            let color = point.color.get_or_insert(Color::new(0, 0, 0));
+            let band = match field {
+                PointField::Red => &mut color.red,
+                PointField::Green => &mut color.green,
+                _ => &mut color.blue,
+            };
+            *band = value as u16;
+        }
+        PointField::Skip => {}
+    }
+}
+
+// an opened point cloud file
+pub trait PointSource {
+    // the CRS the file declares, None if it doesn't have one
+    fn crs(&self) -> Result<Option<WKTStringTransform>>;
+    // for giving merged tiles non-overlapping fids
+    fn number_of_points(&self) -> u64;
+    // which of the optional LAS attributes the points carry
+    fn attributes(&self) -> PointAttributes;
+    // custom per-point attributes (LAS extra bytes)
+    fn extra_bytes(&self) -> Result<Vec<ExtraBytesDescriptor>> {
+        Ok(Vec::new())
+    }
+    // provenance for the parquet footer, only LAS has a header worth keeping
+    fn header_json(&self, _source: &str) -> Option<Value> {
+        None
+    }
+    // hand every point to `on_point` in file order, stopping early once it returns false
+    fn read_points(&mut self, on_point: &mut dyn FnMut(Point) -> Result<bool>) -> Result<()>;
+}
+
+pub struct LasSource {
+    reader: Reader,
+}
+
+impl LasSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let options = ReaderOptions::default().with_laz_parallelism(LazParallelism::Yes);
+        let reader = Reader::with_options(BufReader::new(file), options)?;
+        Ok(LasSource { reader })
+    }
+}
+
+impl PointSource for LasSource {
+    fn crs(&self) -> Result<Option<WKTStringTransform>> {
+        crs_from_header(self.reader.header())
+    }
+
+    fn number_of_points(&self) -> u64 {
+        self.reader.header().number_of_points()
+    }
+
+    fn attributes(&self) -> PointAttributes {
+        PointAttributes::from_format(self.reader.header().point_format())
+    }
+
+    fn extra_bytes(&self) -> Result<Vec<ExtraBytesDescriptor>> {
+        ExtraBytesDescriptor::from_header(self.reader.header())
+    }
+
+    fn header_json(&self, source: &str) -> Option<Value> {
+        Some(header_json(source, self.reader.header(), true))
+    }
+
+    fn read_points(&mut self, on_point: &mut dyn FnMut(Point) -> Result<bool>) -> Result<()> {
+        for point in self.reader.points() {
+            if !on_point(point?)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn open_source(path: &Path, options: &SourceOptions) -> Result<Box<dyn PointSource>> {
+    let format = options
+        .format
+        .or_else(|| SourceFormat::from_path(path))
+        .ok_or_else(|| {
+            QuafferError::InvalidInput(format!(
+                "can't tell what kind of point cloud {} is, pass --input-format",
+                path.display()
+            ))
+        })?;
+    Ok(match format {
+        SourceFormat::Las => Box::new(LasSource::open(path)?),
+        SourceFormat::Ply => Box::new(PlySource::open(path)?),
+        SourceFormat::Csv => Box::new(XyzSource::open(path, &options.csv)?),
+        SourceFormat::E57 => Box::new(E57Source::open(path)?),
+    })
+}
@@ -0,0 +1,226 @@
+// skipping the parts of a parquet file that can't match a query, first whole row groups
+// from the column chunk stats, then pages inside the rest from the page index
+use parquet::arrow::arrow_reader::{RowSelection, RowSelector};
+use parquet::basic::{ConvertedType, LogicalType};
+use parquet::file::metadata::ParquetMetaData;
+use parquet::file::page_index::index::{Index, PageIndex};
+use parquet::file::statistics::Statistics;
+use parquet::schema::types::ColumnDescriptor;
+
+use crate::bbox::Bbox;
+
+// a column a check looks at
+pub enum PrunedColumn {
+    // x/y/z of the point geometry (z falls back to a loose `z` column for 2D points)
+    Coord(&'static str),
+    // dotted parquet path, e.g. `intensity` or `bbox.xmin`
+    Path(String),
+}
+
+// a test on a column's min/max, false means nothing in that range can match
+pub struct RangeCheck {
+    pub column: PrunedColumn,
+    pub can_match: Box<dyn Fn(f64, f64) -> bool>,
+}
+
+impl RangeCheck {
+    pub fn new(column: PrunedColumn, can_match: impl Fn(f64, f64) -> bool + 'static) -> Self {
+        RangeCheck {
+            column,
+            can_match: Box::new(can_match),
+        }
+    }
+
+    // values between `min` and `max` (either end open)
+    pub fn overlapping(column: PrunedColumn, min: Option<f64>, max: Option<f64>) -> Self {
+        RangeCheck::new(column, move |lo, hi| {
+            min.is_none_or(|min| hi >= min) && max.is_none_or(|max| lo <= max)
+        })
+    }
+
+    // parquet paths this check could apply to, most specific first
+    fn paths(&self, geometry_column: &str) -> Vec<String> {
+        match &self.column {
+            PrunedColumn::Coord(coord) => {
+                vec![format!("{geometry_column}.{coord}"), coord.to_string()]
+            }
+            PrunedColumn::Path(path) => vec![path.clone()],
+        }
+    }
+}
+
+// checks for points in `bbox`, against the bbox covering column if there is one
+// and the separated point coords otherwise
+pub fn bbox_checks(bbox: Bbox) -> Vec<RangeCheck> {
+    let path = |name: &str| PrunedColumn::Path(format!("bbox.{name}"));
+    vec![
+        RangeCheck::new(path("xmin"), move |lo, _| lo <= bbox.maxx),
+        RangeCheck::new(path("ymin"), move |lo, _| lo <= bbox.maxy),
+        RangeCheck::new(path("xmax"), move |_, hi| hi >= bbox.minx),
+        RangeCheck::new(path("ymax"), move |_, hi| hi >= bbox.miny),
+        RangeCheck::overlapping(PrunedColumn::Coord("x"), Some(bbox.minx), Some(bbox.maxx)),
+        RangeCheck::overlapping(PrunedColumn::Coord("y"), Some(bbox.miny), Some(bbox.maxy)),
+    ]
+}
+
+// parquet has no unsigned physical types, UInt32/UInt64 columns are stored as
+// Int32/Int64 with an unsigned logical type and their stats need reading back as such
+pub fn is_unsigned(column: &ColumnDescriptor) -> bool {
+    matches!(
+        column.logical_type(),
+        Some(LogicalType::Integer {
+            is_signed: false,
+            ..
+        })
+    ) || matches!(
+        column.converted_type(),
+        ConvertedType::UINT_8
+            | ConvertedType::UINT_16
+            | ConvertedType::UINT_32
+            | ConvertedType::UINT_64
+    )
+}
+
+fn int32_to_f64(value: i32, unsigned: bool) -> f64 {
+    if unsigned {
+        value as u32 as f64
+    } else {
+        value as f64
+    }
+}
+
+fn int64_to_f64(value: i64, unsigned: bool) -> f64 {
+    if unsigned {
+        value as u64 as f64
+    } else {
+        value as f64
+    }
+}
+
+// a column chunk's min/max as numbers, for the number types we can compare
+fn stats_range(stats: &Statistics, unsigned: bool) -> Option<(f64, f64)> {
+    match stats {
+        Statistics::Int32(stats) => Some((
+            int32_to_f64(*stats.min_opt()?, unsigned),
+            int32_to_f64(*stats.max_opt()?, unsigned),
+        )),
+        Statistics::Int64(stats) => Some((
+            int64_to_f64(*stats.min_opt()?, unsigned),
+            int64_to_f64(*stats.max_opt()?, unsigned),
+        )),
+        Statistics::Float(stats) => Some((*stats.min_opt()? as f64, *stats.max_opt()? as f64)),
+        Statistics::Double(stats) => Some((*stats.min_opt()?, *stats.max_opt()?)),
+        _ => None,
+    }
+}
+
+// min/max of each page in a typed column index
+fn ranges<T: Copy>(pages: &[PageIndex<T>], to_f64: impl Fn(T) -> f64) -> Vec<Option<(f64, f64)>> {
+    pages
+        .iter()
+        .map(|page| {
+            page.min
+                .zip(page.max)
+                .map(|(lo, hi)| (to_f64(lo), to_f64(hi)))
+        })
+        .collect()
+}
+
+// each page's min/max, None for pages (or whole columns) without them
+fn page_ranges(index: &Index, unsigned: bool) -> Option<Vec<Option<(f64, f64)>>> {
+    Some(match index {
+        Index::INT32(index) => ranges(&index.indexes, |value| int32_to_f64(value, unsigned)),
+        Index::INT64(index) => ranges(&index.indexes, |value| int64_to_f64(value, unsigned)),
+        Index::FLOAT(index) => ranges(&index.indexes, f64::from),
+        Index::DOUBLE(index) => ranges(&index.indexes, |value| value),
+        _ => return None,
+    })
+}
+
+// the rows of one row group that sit in pages `check` can't rule out
+fn page_selection(
+    metadata: &ParquetMetaData,
+    row_group: usize,
+    column: usize,
+    rows: usize,
+    check: &RangeCheck,
+) -> Option<RowSelection> {
+    let index = metadata.column_index()?.get(row_group)?.get(column)?;
+    let locations = metadata
+        .offset_index()?
+        .get(row_group)?
+        .get(column)?
+        .page_locations();
+    let unsigned = is_unsigned(&metadata.file_metadata().schema_descr().column(column));
+    let ranges = page_ranges(index, unsigned)?;
+    if ranges.len() != locations.len() {
+        return None;
+    }
+    let kept = ranges
+        .iter()
+        .enumerate()
+        .filter(|(_, range)| range.is_none_or(|(lo, hi)| (check.can_match)(lo, hi)))
+        .map(|(page, _)| {
+            let start = locations[page].first_row_index as usize;
+            let end = locations
+                .get(page + 1)
+                .map_or(rows, |next| next.first_row_index as usize);
+            start..end
+        });
+    Some(RowSelection::from_consecutive_ranges(kept, rows))
+}
+
+pub struct Pruned {
+    pub row_groups: Vec<usize>,
+    // rows to read within `row_groups`
+    pub selection: RowSelection,
+}
+
+impl Pruned {
+    pub fn rows_selected(&self) -> usize {
+        self.selection.row_count()
+    }
+}
+
+pub fn prune(metadata: &ParquetMetaData, geometry_column: &str, checks: &[RangeCheck]) -> Pruned {
+    let schema = metadata.file_metadata().schema_descr();
+    // leaf column for each check, checks on columns the file doesn't have just drop out
+    let resolved: Vec<(usize, bool, &RangeCheck)> = checks
+        .iter()
+        .filter_map(|check| {
+            let paths = check.paths(geometry_column);
+            let column = paths.iter().find_map(|path| {
+                (0..schema.num_columns()).find(|&col| schema.column(col).path().string() == *path)
+            })?;
+            Some((column, is_unsigned(&schema.column(column)), check))
+        })
+        .collect();
+
+    let mut row_groups = Vec::new();
+    let mut selectors: Vec<RowSelector> = Vec::new();
+    for (idx, row_group) in metadata.row_groups().iter().enumerate() {
+        let rows = row_group.num_rows() as usize;
+        let might_match = resolved.iter().all(|(column, unsigned, check)| {
+            row_group
+                .column(*column)
+                .statistics()
+                .and_then(|stats| stats_range(stats, *unsigned))
+                .is_none_or(|(lo, hi)| (check.can_match)(lo, hi))
+        });
+        if !might_match {
+            continue;
+        }
+        row_groups.push(idx);
+        let mut selection = RowSelection::from(vec![RowSelector::select(rows)]);
+        for (column, _, check) in &resolved {
+            if let Some(pages) = page_selection(metadata, idx, *column, rows, check) {
+                selection = selection.intersection(&pages);
+            }
+        }
+        selectors.extend(Vec::<RowSelector>::from(selection));
+    }
+    Pruned {
+        row_groups,
+        selection: RowSelection::from(selectors),
+    }
+}
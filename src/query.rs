@@ -0,0 +1,606 @@
+// pulling the points that match a query out of an imported geoparquet file:
+// a bbox or polygon, a z range, classes and simple `column op value` predicates.
+// whatever the footer stats can rule out never gets read, the rest is filtered per point
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::UInt8Type;
+use arrow_array::{Array, BooleanArray, RecordBatch, RecordBatchReader, StringArray, UInt8Array};
+use arrow_select::filter::filter_record_batch;
+use parquet::file::metadata::KeyValue;
+use serde_json::json;
+
+use crate::bbox::Bbox;
+use crate::clip::{ClipRegion, PreparedClip};
+use crate::error::{QuafferError, Result};
+use crate::gpq_to_laz::{Classes, legacy_class_codes};
+use crate::laz_to_gpq::decode_class_codes;
+use crate::point_filter::ClassCode;
+use crate::point_output::{OutputCrs, OutputFormat, carried_footer, open_sink, pick_format};
+use crate::pruning::{PrunedColumn, RangeCheck, bbox_checks};
+use crate::read_parq::{Coords, Numbers, PointFile, open_points};
+
+// parquet footer key the query gets recorded under in geoparquet outputs
+pub const QUERY_METADATA_KEY: &str = "point_quaffer:query";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Number(number) => write!(f, "{number}"),
+            Literal::Bool(flag) => write!(f, "{flag}"),
+            Literal::Text(text) => write!(f, "'{text}'"),
+        }
+    }
+}
+
+// `column op value`, e.g. `intensity > 200` or `is_withheld = false`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub column: String,
+    pub op: Op,
+    pub value: Literal,
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.column, self.op, self.value)
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let bad = || {
+            QuafferError::InvalidInput(format!(
+                "couldn't read `{s}` as a predicate, expected `column op value` \
+                 with op one of =, !=, <, <=, >, >="
+            ))
+        };
+        let start = s.find(['=', '!', '<', '>']).ok_or_else(bad)?;
+        let rest = &s[start..];
+        let (op, len) = if rest.starts_with("==") {
+            (Op::Eq, 2)
+        } else if rest.starts_with("!=") || rest.starts_with("<>") {
+            (Op::Ne, 2)
+        } else if rest.starts_with("<=") {
+            (Op::Le, 2)
+        } else if rest.starts_with(">=") {
+            (Op::Ge, 2)
+        } else if rest.starts_with('=') {
+            (Op::Eq, 1)
+        } else if rest.starts_with('<') {
+            (Op::Lt, 1)
+        } else if rest.starts_with('>') {
+            (Op::Gt, 1)
+        } else {
+            return Err(bad());
+        };
+        let column = s[..start].trim();
+        let value = s[start + len..].trim();
+        if column.is_empty() || value.is_empty() {
+            return Err(bad());
+        }
+        let value = if let Some(text) = value
+            .strip_prefix('\'')
+            .and_then(|value| value.strip_suffix('\''))
+            .or_else(|| {
+                value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+            }) {
+            Literal::Text(text.to_string())
+        } else if let Ok(number) = value.parse::<f64>() {
+            Literal::Number(number)
+        } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            Literal::Bool(value.eq_ignore_ascii_case("true"))
+        } else {
+            Literal::Text(value.to_string())
+        };
+        Ok(Predicate {
+            column: column.to_string(),
+            op,
+            value,
+        })
+    }
+}
+
+// "intensity > 200 AND return_number = 1", AND in any case, no OR.
+// an AND inside quoted text (`classification = 'rail and road'`) doesn't split
+pub fn parse_predicates(filter: &str) -> Result<Vec<Predicate>> {
+    const SEPARATOR: &str = " and ";
+    let lower = filter.to_ascii_lowercase();
+    let mut predicates = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    for (idx, c) in filter.char_indices() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if idx >= start && lower[idx..].starts_with(SEPARATOR) => {
+                predicates.push(filter[start..idx].parse()?);
+                start = idx + SEPARATOR.len();
+            }
+            None => {}
+        }
+    }
+    predicates.push(filter[start..].parse()?);
+    Ok(predicates)
+}
+
+impl Predicate {
+    // what the footer stats can rule out, only numbers can be ranged
+    fn range_check(&self) -> Option<RangeCheck> {
+        let Literal::Number(value) = self.value else {
+            return None;
+        };
+        let column = match self.column.as_str() {
+            "x" => PrunedColumn::Coord("x"),
+            "y" => PrunedColumn::Coord("y"),
+            "z" => PrunedColumn::Coord("z"),
+            other => PrunedColumn::Path(other.to_string()),
+        };
+        Some(match self.op {
+            Op::Eq => RangeCheck::overlapping(column, Some(value), Some(value)),
+            Op::Ne => RangeCheck::new(column, move |lo, hi| lo != value || hi != value),
+            Op::Lt => RangeCheck::new(column, move |lo, _| lo < value),
+            Op::Le => RangeCheck::new(column, move |lo, _| lo <= value),
+            Op::Gt => RangeCheck::new(column, move |_, hi| hi > value),
+            Op::Ge => RangeCheck::new(column, move |_, hi| hi >= value),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    pub bbox: Option<Bbox>,
+    // polygon(s) in a GeoJSON or WKT file, in the points' CRS
+    pub polygon: Option<PathBuf>,
+    pub z_min: Option<f64>,
+    pub z_max: Option<f64>,
+    // empty means every class
+    pub classes: Vec<ClassCode>,
+    pub predicates: Vec<Predicate>,
+    // None writes to stdout
+    pub output: Option<PathBuf>,
+    // None picks from the output's extension, CSV for stdout
+    pub format: Option<OutputFormat>,
+    pub overwrite: bool,
+}
+
+impl QueryOptions {
+    fn to_metadata_json(&self) -> String {
+        let predicates: Vec<String> = self.predicates.iter().map(|p| p.to_string()).collect();
+        json!({
+            "bbox": self.bbox.map(|bbox| [bbox.minx, bbox.miny, bbox.maxx, bbox.maxy]),
+            "polygon": self.polygon.as_ref().map(|path| path.to_string_lossy()),
+            "z_min": self.z_min,
+            "z_max": self.z_max,
+            "classes": self.classes.iter().map(|class| class.0).collect::<Vec<_>>(),
+            "where": predicates,
+        })
+        .to_string()
+    }
+}
+
+// a predicate's column in one batch
+enum Operand<'a> {
+    Coord(usize),
+    Numbers(Numbers<'a>),
+    // classification, decoded from the dictionary labels
+    Codes(UInt8Array),
+    Flags(&'a BooleanArray),
+    Text(&'a StringArray),
+}
+
+struct BoundPredicate<'a> {
+    operand: Operand<'a>,
+    op: Op,
+    value: Literal,
+}
+
+impl<'a> BoundPredicate<'a> {
+    fn new(batch: &'a RecordBatch, predicate: &Predicate) -> Result<Self> {
+        let mismatch = |kind: &str| {
+            QuafferError::InvalidInput(format!(
+                "can't compare {kind} column `{}` with {}",
+                predicate.column, predicate.value
+            ))
+        };
+        let coord = ["x", "y", "z"]
+            .iter()
+            .position(|axis| *axis == predicate.column);
+        let mut value = predicate.value.clone();
+        let operand = match (batch.column_by_name(&predicate.column), coord) {
+            (None, Some(axis)) => Operand::Coord(axis),
+            (None, None) => {
+                return Err(QuafferError::InvalidInput(format!(
+                    "no column named `{}` to filter on",
+                    predicate.column
+                )));
+            }
+            (Some(column), _) => {
+                if let Some(flags) = column.as_boolean_opt() {
+                    Operand::Flags(flags)
+                } else if let Some(names) = column.as_string_opt::<i32>() {
+                    Operand::Text(names)
+                } else if let Some(dictionary) = column.as_dictionary_opt::<UInt8Type>() {
+                    // classification, where `= ground` reads better than `= 2`
+                    if let Literal::Text(name) = &value {
+                        value = Literal::Number(name.parse::<ClassCode>()?.0.into());
+                    }
+                    Operand::Codes(decode_class_codes(dictionary)?)
+                } else {
+                    Numbers::from_batch(batch, &predicate.column)?
+                        .map(Operand::Numbers)
+                        .ok_or_else(|| mismatch("an unknown"))?
+                }
+            }
+        };
+        match (&operand, &value) {
+            (Operand::Flags(_), Literal::Bool(_))
+            | (Operand::Text(_), Literal::Text(_))
+            | (Operand::Coord(_) | Operand::Numbers(_) | Operand::Codes(_), Literal::Number(_)) => {
+            }
+            (Operand::Flags(_), _) => return Err(mismatch("boolean")),
+            (Operand::Text(_), _) => return Err(mismatch("text")),
+            _ => return Err(mismatch("numeric")),
+        }
+        Ok(BoundPredicate {
+            operand,
+            op: predicate.op,
+            value,
+        })
+    }
+
+    // nulls (and NaNs) never match
+    fn matches(&self, idx: usize, xyz: (f64, f64, Option<f64>)) -> bool {
+        let ordering = match (&self.operand, &self.value) {
+            (Operand::Coord(axis), Literal::Number(value)) => {
+                let coord = match axis {
+                    0 => Some(xyz.0),
+                    1 => Some(xyz.1),
+                    _ => xyz.2,
+                };
+                coord.and_then(|coord| coord.partial_cmp(value))
+            }
+            (Operand::Numbers(numbers), Literal::Number(value)) => numbers
+                .float(idx)
+                .and_then(|number| number.partial_cmp(value)),
+            (Operand::Codes(codes), Literal::Number(value)) => codes
+                .is_valid(idx)
+                .then(|| f64::from(codes.value(idx)).partial_cmp(value))
+                .flatten(),
+            (Operand::Flags(flags), Literal::Bool(value)) => {
+                flags.is_valid(idx).then(|| flags.value(idx).cmp(value))
+            }
+            (Operand::Text(names), Literal::Text(value)) => names
+                .is_valid(idx)
+                .then(|| names.value(idx).cmp(value.as_str())),
+            _ => None,
+        };
+        ordering.is_some_and(|ordering| self.op.test(ordering))
+    }
+}
+
+// which rows of a batch make it through every part of the query
+fn matching_rows(
+    batch: &RecordBatch,
+    geometry_column: &str,
+    options: &QueryOptions,
+    clip: Option<&PreparedClip>,
+    class_codes: &HashMap<String, u8>,
+) -> Result<BooleanArray> {
+    let coords = Coords::from_batch(batch, geometry_column)?;
+    // 2D points from older imports keep z in its own column
+    let z_column = Numbers::from_batch(batch, "z")?;
+    let classes = if options.classes.is_empty() {
+        None
+    } else {
+        let column = batch.column_by_name("classification").ok_or_else(|| {
+            QuafferError::InvalidInput("no classification column to filter on".to_string())
+        })?;
+        Some(Classes::from_column(column, class_codes)?.ok_or_else(|| {
+            QuafferError::InvalidInput(format!(
+                "classification column is {}, expected class codes or names",
+                column.data_type()
+            ))
+        })?)
+    };
+    let predicates = options
+        .predicates
+        .iter()
+        .map(|predicate| BoundPredicate::new(batch, predicate))
+        .collect::<Result<Vec<_>>>()?;
+    let has_z_range = options.z_min.is_some() || options.z_max.is_some();
+
+    let keep: Vec<bool> = (0..batch.num_rows())
+        .map(|idx| {
+            let (x, y, z) = coords.xyz(idx);
+            let z = z.or_else(|| z_column.as_ref().and_then(|column| column.float(idx)));
+            if options.bbox.is_some_and(|bbox| !bbox.contains(x, y)) {
+                return false;
+            }
+            if clip.is_some_and(|clip| !clip.contains(x, y)) {
+                return false;
+            }
+            if has_z_range
+                && !z.is_some_and(|z| {
+                    options.z_min.is_none_or(|min| z >= min)
+                        && options.z_max.is_none_or(|max| z <= max)
+                })
+            {
+                return false;
+            }
+            if let Some(classes) = &classes {
+                let code = classes.code(idx);
+                if !options.classes.iter().any(|class| class.0 == code) {
+                    return false;
+                }
+            }
+            predicates
+                .iter()
+                .all(|predicate| predicate.matches(idx, (x, y, z)))
+        })
+        .collect();
+    Ok(BooleanArray::from(keep))
+}
+
+pub fn query(filepath: &str, options: &QueryOptions) -> Result<u64> {
+    let clip = options
+        .polygon
+        .as_ref()
+        .map(|path| ClipRegion::from_file(path, None)?.prepare(None))
+        .transpose()?;
+
+    // everything that can be pushed into row group and page pruning
+    let mut checks = Vec::new();
+    if let Some(bbox) = options.bbox {
+        checks.extend(bbox_checks(bbox));
+    }
+    if let Some(clip) = &clip {
+        checks.extend(bbox_checks(clip.bounds()));
+    }
+    if options.z_min.is_some() || options.z_max.is_some() {
+        checks.push(RangeCheck::overlapping(
+            PrunedColumn::Coord("z"),
+            options.z_min,
+            options.z_max,
+        ));
+    }
+    checks.extend(options.predicates.iter().filter_map(Predicate::range_check));
+
+    let PointFile {
+        reader,
+        geometry_column,
+        metadata,
+        crs,
+        pruned,
+    } = open_points(filepath, &checks)?;
+    // stdout might be the output, so progress goes to stderr
+    if let Some((row_groups, rows)) = pruned {
+        eprintln!(
+            "Reading {rows} of {} rows ({row_groups} of {} row groups) after pruning",
+            metadata.file_metadata().num_rows(),
+            metadata.num_row_groups()
+        );
+    }
+
+    let format = pick_format(options.format, options.output.as_deref())?;
+    let crs = crs.as_ref().map(OutputCrs::from_projjson).transpose()?;
+    let mut footer = carried_footer(&metadata);
+    footer.retain(|key_value| key_value.key != QUERY_METADATA_KEY);
+    footer.push(KeyValue::new(
+        QUERY_METADATA_KEY.to_string(),
+        options.to_metadata_json(),
+    ));
+    let mut sink = open_sink(
+        format,
+        options.output.as_deref(),
+        &reader.schema(),
+        &geometry_column,
+        crs.as_ref(),
+        footer,
+        options.overwrite,
+    )?;
+
+    let class_codes = legacy_class_codes();
+    let mut points_matched: u64 = 0;
+    for batch in reader {
+        let batch = batch?;
+        let keep = matching_rows(
+            &batch,
+            &geometry_column,
+            options,
+            clip.as_ref(),
+            &class_codes,
+        )?;
+        let batch = filter_record_batch(&batch, &keep)?;
+        if batch.num_rows() == 0 {
+            continue;
+        }
+        points_matched += batch.num_rows() as u64;
+        sink.write_batch(&batch)?;
+    }
+    sink.finish()?;
+    eprintln!("{points_matched} points matched");
+    Ok(points_matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predicate(column: &str, op: Op, value: Literal) -> Predicate {
+        Predicate {
+            column: column.to_string(),
+            op,
+            value,
+        }
+    }
+
+    #[test]
+    fn predicates_parse() {
+        let cases = [
+            (
+                "intensity > 200",
+                predicate("intensity", Op::Gt, Literal::Number(200.0)),
+            ),
+            ("z>=-1.5", predicate("z", Op::Ge, Literal::Number(-1.5))),
+            (
+                "return_number == 1",
+                predicate("return_number", Op::Eq, Literal::Number(1.0)),
+            ),
+            (
+                "user_data <> 3",
+                predicate("user_data", Op::Ne, Literal::Number(3.0)),
+            ),
+            (
+                "gps_time != 0",
+                predicate("gps_time", Op::Ne, Literal::Number(0.0)),
+            ),
+            ("x <= 10", predicate("x", Op::Le, Literal::Number(10.0))),
+            ("y < 10", predicate("y", Op::Lt, Literal::Number(10.0))),
+            (
+                "is_withheld = FALSE",
+                predicate("is_withheld", Op::Eq, Literal::Bool(false)),
+            ),
+            (
+                "classification = ground",
+                predicate(
+                    "classification",
+                    Op::Eq,
+                    Literal::Text("ground".to_string()),
+                ),
+            ),
+            (
+                "name = 'a = b'",
+                predicate("name", Op::Eq, Literal::Text("a = b".to_string())),
+            ),
+            (
+                "name = \"42\"",
+                predicate("name", Op::Eq, Literal::Text("42".to_string())),
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(text.parse::<Predicate>().unwrap(), expected, "{text}");
+        }
+        for bad in ["intensity", "> 200", "intensity >", "intensity ! 3", ""] {
+            assert!(bad.parse::<Predicate>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn predicates_split_on_and() {
+        let predicates =
+            parse_predicates("intensity > 200 AND return_number = 1 and z < 5").unwrap();
+        assert_eq!(
+            predicates,
+            [
+                predicate("intensity", Op::Gt, Literal::Number(200.0)),
+                predicate("return_number", Op::Eq, Literal::Number(1.0)),
+                predicate("z", Op::Lt, Literal::Number(5.0)),
+            ]
+        );
+        // the column name `sand` isn't an AND
+        assert_eq!(parse_predicates("sand = 1").unwrap().len(), 1);
+        assert!(parse_predicates("intensity > 200 AND z").is_err());
+        assert_eq!(
+            parse_predicates("name = 'rail and road' AND z < 5").unwrap(),
+            [
+                predicate("name", Op::Eq, Literal::Text("rail and road".to_string())),
+                predicate("z", Op::Lt, Literal::Number(5.0)),
+            ]
+        );
+        assert_eq!(
+            parse_predicates("name = \"a AND b\"").unwrap(),
+            [predicate(
+                "name",
+                Op::Eq,
+                Literal::Text("a AND b".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn range_checks_rule_out_ranges() {
+        let check = |text: &str| text.parse::<Predicate>().unwrap().range_check().unwrap();
+
+        let eq = check("intensity = 10");
+        assert!(matches!(&eq.column, PrunedColumn::Path(path) if path == "intensity"));
+        assert!((eq.can_match)(0.0, 10.0));
+        assert!((eq.can_match)(10.0, 20.0));
+        assert!(!(eq.can_match)(11.0, 20.0));
+
+        let ne = check("intensity != 10");
+        assert!(!(ne.can_match)(10.0, 10.0));
+        assert!((ne.can_match)(10.0, 11.0));
+
+        let lt = check("z < 5");
+        assert!(matches!(lt.column, PrunedColumn::Coord("z")));
+        assert!((lt.can_match)(4.0, 100.0));
+        assert!(!(lt.can_match)(5.0, 100.0));
+        assert!((check("z <= 5").can_match)(5.0, 100.0));
+
+        let gt = check("x > 5");
+        assert!(!(gt.can_match)(0.0, 5.0));
+        assert!((check("x >= 5").can_match)(0.0, 5.0));
+
+        // only numbers can be ranged
+        assert!(check_none("is_withheld = true"));
+        assert!(check_none("classification = ground"));
+    }
+
+    fn check_none(text: &str) -> bool {
+        text.parse::<Predicate>().unwrap().range_check().is_none()
+    }
+}
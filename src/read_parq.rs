@@ -1,89 +1,389 @@
 use std::fs::File;
+use std::sync::Arc;
 
+use arrow_array::Array;
+use arrow_array::FixedSizeListArray;
 use arrow_array::Float64Array;
+use arrow_array::PrimitiveArray;
 use arrow_array::RecordBatch;
 use arrow_array::RecordBatchReader;
-use arrow_array::StringArray;
-use arrow_array::StructArray;
-use arrow_schema::ArrowError;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Float32Type, Float64Type, Int8Type, Int16Type, Int32Type, Int64Type, UInt8Type, UInt16Type,
+    UInt32Type, UInt64Type,
+};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::DataType;
 use geoparquet::reader::{GeoParquetReaderBuilder, GeoParquetRecordBatchReader};
-use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::arrow_reader::{ArrowReaderOptions, ParquetRecordBatchReaderBuilder};
+use parquet::file::metadata::{KeyValue, ParquetMetaData, RowGroupMetaData};
+use parquet::file::statistics::Statistics;
+
+use crate::bbox::Bbox;
+use crate::error::{QuafferError, Result};
+use crate::footer_keys::{BBOX_COLUMN, FOOTER_KEY_PREFIX, LAS_HEADER_METADATA_KEY};
+use crate::pruning::{RangeCheck, bbox_checks, prune};
 
 const BATCH_ROW_SIZE: usize = 65536; // this was the val in the example
 const DEBUG_PRINT_FREQ: usize = 10000; // print debug record info every <this num> of rows
 
-pub fn read(filepath: &String) {
+// point coords, from either geoarrow coord layout (2D or 3D)
+pub enum Coords<'a> {
+    Separated {
+        x: &'a Float64Array,
+        y: &'a Float64Array,
+        z: Option<&'a Float64Array>,
+    },
+    Interleaved {
+        points: &'a FixedSizeListArray,
+        values: &'a Float64Array,
+    },
+}
+
+impl<'a> Coords<'a> {
+    pub fn from_batch(batch: &'a RecordBatch, geometry_column: &str) -> Result<Self> {
+        let column = batch.column_by_name(geometry_column).ok_or_else(|| {
+            QuafferError::InvalidInput(format!("missing geometry column `{geometry_column}`"))
+        })?;
+        let bad_coords = || {
+            QuafferError::InvalidInput(format!(
+                "geometry column `{geometry_column}` is {}, expected Float64 points",
+                column.data_type()
+            ))
+        };
+        if let Some(points) = column.as_struct_opt() {
+            let coord = |name: &str| {
+                points
+                    .column_by_name(name)
+                    .and_then(|coord| coord.as_primitive_opt::<Float64Type>())
+            };
+            return Ok(Coords::Separated {
+                x: coord("x").ok_or_else(bad_coords)?,
+                y: coord("y").ok_or_else(bad_coords)?,
+                z: coord("z"),
+            });
+        }
+        let points = column.as_fixed_size_list_opt().ok_or_else(bad_coords)?;
+        Ok(Coords::Interleaved {
+            points,
+            values: points
+                .values()
+                .as_primitive_opt::<Float64Type>()
+                .ok_or_else(bad_coords)?,
+        })
+    }
+
+    pub fn xyz(&self, idx: usize) -> (f64, f64, Option<f64>) {
+        match self {
+            Coords::Separated { x, y, z } => (x.value(idx), y.value(idx), z.map(|z| z.value(idx))),
+            Coords::Interleaved { points, values } => {
+                let start = points.value_offset(idx) as usize;
+                let z = (points.value_length() > 2).then(|| values.value(start + 2));
+                (values.value(start), values.value(start + 1), z)
+            }
+        }
+    }
+}
+
+// a numeric column in whatever width it was written with
+// (LAS types by default, Int64/Float64 from --legacy-schema imports,
+// and any of them for extra bytes attributes)
+pub enum Numbers<'a> {
+    Int8(&'a PrimitiveArray<Int8Type>),
+    Int16(&'a PrimitiveArray<Int16Type>),
+    Int32(&'a PrimitiveArray<Int32Type>),
+    UInt8(&'a PrimitiveArray<UInt8Type>),
+    UInt16(&'a PrimitiveArray<UInt16Type>),
+    UInt32(&'a PrimitiveArray<UInt32Type>),
+    UInt64(&'a PrimitiveArray<UInt64Type>),
+    Int64(&'a PrimitiveArray<Int64Type>),
+    Float32(&'a PrimitiveArray<Float32Type>),
+    Float64(&'a PrimitiveArray<Float64Type>),
+}
+
+impl<'a> Numbers<'a> {
+    pub fn from_batch(batch: &'a RecordBatch, name: &str) -> Result<Option<Self>> {
+        let Some(column) = batch.column_by_name(name) else {
+            return Ok(None);
+        };
+        let numbers = match column.data_type() {
+            DataType::Int8 => Numbers::Int8(column.as_primitive()),
+            DataType::Int16 => Numbers::Int16(column.as_primitive()),
+            DataType::Int32 => Numbers::Int32(column.as_primitive()),
+            DataType::UInt8 => Numbers::UInt8(column.as_primitive()),
+            DataType::UInt16 => Numbers::UInt16(column.as_primitive()),
+            DataType::UInt32 => Numbers::UInt32(column.as_primitive()),
+            DataType::UInt64 => Numbers::UInt64(column.as_primitive()),
+            DataType::Int64 => Numbers::Int64(column.as_primitive()),
+            DataType::Float32 => Numbers::Float32(column.as_primitive()),
+            DataType::Float64 => Numbers::Float64(column.as_primitive()),
+            other => {
+                return Err(QuafferError::InvalidInput(format!(
+                    "column `{name}` is {other}, expected a number"
+                )));
+            }
+        };
+        Ok(Some(numbers))
+    }
+
+    fn array(&self) -> &dyn Array {
+        match self {
+            Numbers::Int8(array) => *array,
+            Numbers::Int16(array) => *array,
+            Numbers::Int32(array) => *array,
+            Numbers::UInt8(array) => *array,
+            Numbers::UInt16(array) => *array,
+            Numbers::UInt32(array) => *array,
+            Numbers::UInt64(array) => *array,
+            Numbers::Int64(array) => *array,
+            Numbers::Float32(array) => *array,
+            Numbers::Float64(array) => *array,
+        }
+    }
+
+    pub fn float(&self, idx: usize) -> Option<f64> {
+        if self.array().is_null(idx) {
+            return None;
+        }
+        Some(match self {
+            Numbers::Int8(array) => array.value(idx).into(),
+            Numbers::Int16(array) => array.value(idx).into(),
+            Numbers::Int32(array) => array.value(idx).into(),
+            Numbers::UInt8(array) => array.value(idx) as f64,
+            Numbers::UInt16(array) => array.value(idx) as f64,
+            Numbers::UInt32(array) => array.value(idx) as f64,
+            Numbers::UInt64(array) => array.value(idx) as f64,
+            Numbers::Int64(array) => array.value(idx) as f64,
+            Numbers::Float32(array) => array.value(idx) as f64,
+            Numbers::Float64(array) => array.value(idx),
+        })
+    }
+
+    // negative and fractional values don't come up for the integer LAS fields,
+    // but signed ints and floats get clamped rather than wrapped if they do
+    pub fn int(&self, idx: usize) -> Option<u64> {
+        if self.array().is_null(idx) {
+            return None;
+        }
+        Some(match self {
+            Numbers::Int8(array) => array.value(idx).max(0) as u64,
+            Numbers::Int16(array) => array.value(idx).max(0) as u64,
+            Numbers::Int32(array) => array.value(idx).max(0) as u64,
+            Numbers::UInt8(array) => array.value(idx).into(),
+            Numbers::UInt16(array) => array.value(idx).into(),
+            Numbers::UInt32(array) => array.value(idx).into(),
+            Numbers::UInt64(array) => array.value(idx),
+            Numbers::Int64(array) => array.value(idx).max(0) as u64,
+            Numbers::Float32(array) => array.value(idx) as u64,
+            Numbers::Float64(array) => array.value(idx) as u64,
+        })
+    }
+}
+
+// row group bounds from the min/max stats of the bbox covering column,
+// None if the file has no bbox column or was written without statistics
+pub fn row_group_bounds(row_group: &RowGroupMetaData) -> Option<Bbox> {
+    let stat = |name: &str, want_min: bool| {
+        let column = row_group
+            .columns()
+            .iter()
+            .find(|column| column.column_path().string() == format!("bbox.{name}"))?;
+        match column.statistics()? {
+            Statistics::Double(stats) if want_min => stats.min_opt().copied(),
+            Statistics::Double(stats) => stats.max_opt().copied(),
+            _ => None,
+        }
+    };
+    Some(Bbox {
+        minx: stat("xmin", true)?,
+        miny: stat("ymin", true)?,
+        maxx: stat("xmax", false)?,
+        maxy: stat("ymax", false)?,
+    })
+}
+
+// where the points came from, summarized (the VLR payloads are left out, they're mostly binary)
+fn print_las_headers(headers: &str) {
+    let Ok(serde_json::Value::Array(headers)) = serde_json::from_str(headers) else {
+        println!("  (couldn't parse the LAS header metadata)");
+        return;
+    };
+    for header in &headers {
+        let field = |name: &str| header[name].to_string();
+        println!(
+            "  {}",
+            header["source"].as_str().unwrap_or("unknown source")
+        );
+        println!(
+            "    LAS {} point format {}, {} points, from {} / {} on {}",
+            header["version"].as_str().unwrap_or("?"),
+            field("point_format"),
+            field("point_count"),
+            field("system_identifier"),
+            field("generating_software"),
+            field("creation_date"),
+        );
+        println!(
+            "    file source id {}, scale {}, offset {}",
+            field("file_source_id"),
+            field("scale"),
+            field("offset")
+        );
+        let vlrs = header["vlrs"].as_array().into_iter().flatten();
+        let evlrs = header["evlrs"].as_array().into_iter().flatten();
+        for vlr in vlrs.chain(evlrs) {
+            println!(
+                "    VLR {}/{} ({} bytes) {}",
+                vlr["user_id"].as_str().unwrap_or("?"),
+                vlr["record_id"],
+                vlr["length"],
+                vlr["description"].as_str().unwrap_or_default()
+            );
+        }
+    }
+}
+
+fn print_footer_metadata(key_values: &[KeyValue]) {
+    for key_value in key_values {
+        if !key_value.key.starts_with(FOOTER_KEY_PREFIX) {
+            continue;
+        }
+        let value = key_value.value.as_deref().unwrap_or_default();
+        if key_value.key == LAS_HEADER_METADATA_KEY {
+            println!("Source LAS headers:");
+            print_las_headers(value);
+        } else {
+            println!("{}: {value}", key_value.key);
+        }
+    }
+}
+
+// a geoparquet point file ready to stream, plus what its footer says
+pub struct PointFile {
+    pub reader: GeoParquetRecordBatchReader,
+    pub geometry_column: String,
+    pub metadata: Arc<ParquetMetaData>,
+    // PROJJSON of the geometry column, None if the file doesn't say
+    pub crs: Option<serde_json::Value>,
+    // what pruning left to read, None if there were no checks
+    pub pruned: Option<(usize, usize)>,
+}
+
+// open a geoparquet file for streaming points, skipping any row groups and pages
+// `checks` rule out (the rows that are left still need filtering)
+pub fn open_points(filepath: &str, checks: &[RangeCheck]) -> Result<PointFile> {
+    let file = File::open(filepath)?;
+    // page min/max only get loaded when there's something to prune with
+    let options = ArrowReaderOptions::new().with_page_index(!checks.is_empty());
+    let mut builder = ParquetRecordBatchReaderBuilder::try_new_with_options(file, options)?;
+    let metadata = builder.metadata().clone();
+    let geoparquet_metadata = builder.geoparquet_metadata().ok_or_else(|| {
+        QuafferError::InvalidInput(format!("{filepath} doesn't have any geoparquet metadata"))
+    })??;
+    // newer imports write an `xyz` point column, older ones an `xy` point plus a `z` column,
+    // anything else is fine too as long as it's points
+    let geometry_column = geoparquet_metadata.primary_column.clone();
+    let crs = geoparquet_metadata
+        .columns
+        .get(&geometry_column)
+        .and_then(|column| column.crs.clone())
+        .filter(|crs| !crs.is_null());
+    let geoarrow_schema =
+        builder.geoarrow_schema(&geoparquet_metadata, true, Default::default())?;
+    let mut pruned = None;
+    if !checks.is_empty() {
+        let kept = prune(&metadata, &geometry_column, checks);
+        pruned = Some((kept.row_groups.len(), kept.rows_selected()));
+        builder = builder
+            .with_row_groups(kept.row_groups)
+            .with_row_selection(kept.selection);
+    }
+    let parquet_reader = builder.with_batch_size(BATCH_ROW_SIZE).build()?;
+    let reader = GeoParquetRecordBatchReader::try_new(parquet_reader, geoarrow_schema)?;
+    Ok(PointFile {
+        reader,
+        geometry_column,
+        metadata,
+        crs,
+        pruned,
+    })
+}
+
+pub fn read(filepath: &String, bbox: Option<Bbox>) -> Result<()> {
     println!("Opening parquet file at {}...", filepath);
-    let file = File::open(filepath).unwrap();
-    let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
-    println!("Setting up geoparquet reader...");
-    let geoparquet_metadata = builder.geoparquet_metadata().unwrap().unwrap();
-    let geoarrow_schema = builder
-        .geoarrow_schema(&geoparquet_metadata, true, Default::default())
-        .unwrap();
-    println!("Start reader...");
-    let parquet_reader = builder.with_batch_size(BATCH_ROW_SIZE).build().unwrap();
-    let geoparquet_reader =
-        GeoParquetRecordBatchReader::try_new(parquet_reader, geoarrow_schema).unwrap();
+    let checks = bbox.map(bbox_checks).unwrap_or_default();
+    let PointFile {
+        reader: geoparquet_reader,
+        geometry_column,
+        metadata,
+        pruned,
+        ..
+    } = open_points(filepath, &checks)?;
+    if let Some(key_values) = metadata.file_metadata().key_value_metadata() {
+        print_footer_metadata(key_values);
+    }
+    if let Some((row_groups, rows)) = pruned {
+        println!(
+            "Reading {rows} of {} rows ({row_groups} of {} row groups) for the bbox",
+            metadata.file_metadata().num_rows(),
+            metadata.num_row_groups()
+        );
+    }
     let schema = geoparquet_reader.schema();
     println!("Schema info:\n{}", schema);
+    // every other column gets printed as whatever type it is
+    // (the bbox covering column just repeats the coords)
+    let attribute_columns: Vec<(usize, String)> = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| field.name() != &geometry_column && field.name() != BBOX_COLUMN)
+        .map(|(i, field)| (i, field.name().clone()))
+        .collect();
+    let format_options = FormatOptions::default().with_null("null");
     println!("Starting batch parsing...");
-    let batches = geoparquet_reader
-        .collect::<Result<Vec<RecordBatch>, ArrowError>>()
-        .unwrap();
-    let batch_count = batches.len();
-    for (batch_i, batch) in batches.iter().enumerate() {
-        // Get the xy column as a StructArray
-        let xy_col_arr_ref = batch.column_by_name("xy").unwrap();
-        let xy_struct = xy_col_arr_ref
-            .as_any()
-            .downcast_ref::<StructArray>()
-            .unwrap();
-
-        // Get x and y arrays from the struct
-        let x_array = xy_struct
-            .column_by_name("x")
-            .unwrap()
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .unwrap();
-        let y_array = xy_struct
-            .column_by_name("y")
-            .unwrap()
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .unwrap();
-        // Grab z column directly
-        let z_array = batch
-            .column_by_name("z")
-            .unwrap()
-            .as_any()
-            .downcast_ref::<Float64Array>()
-            .unwrap();
-        // Grab point classifaction too
-        let class_array = batch
-            .column_by_name("classification")
-            .unwrap()
-            .as_any()
-            .downcast_ref::<StringArray>()
-            .unwrap();
-
-        let row_count = if batch_i < batch_count - 1 {
-            BATCH_ROW_SIZE
-        } else {
-            batch.num_rows() // last batch may not be full
-        };
+    let mut row: usize = 0;
+    let mut matched: usize = 0;
+    for batch in geoparquet_reader {
+        let batch = batch?;
+        let coords = Coords::from_batch(&batch, &geometry_column)?;
+        let formatters = attribute_columns
+            .iter()
+            .map(|(i, name)| {
+                ArrayFormatter::try_new(batch.column(*i).as_ref(), &format_options)
+                    .map(|formatter| (name.as_str(), formatter))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        for i in 0..row_count {
+        for i in 0..batch.num_rows() {
+            let row_index = row;
+            row += 1;
+            let (x, y, z) = coords.xyz(i);
+            // row groups only narrow it down, the points still need checking
+            if let Some(bbox) = bbox
+                && !bbox.contains(x, y)
+            {
+                continue;
+            }
+            matched += 1;
             // Print some sample rows
-            if i % DEBUG_PRINT_FREQ == 0 {
-                let x = x_array.value(i);
-                let y = y_array.value(i);
-                let z = z_array.value(i);
-                let class = class_array.value(i);
-                println!("Row {}: x={}, y={}, z={} - {}", i, x, y, z, class);
+            if row_index.is_multiple_of(DEBUG_PRINT_FREQ) {
+                let attributes: Vec<String> = formatters
+                    .iter()
+                    .map(|(name, formatter)| format!("{name}={}", formatter.value(i)))
+                    .collect();
+                let z = z.map(|z| format!(", z={z}")).unwrap_or_default();
+                println!(
+                    "Row {row_index}: x={x}, y={y}{z} - {}",
+                    attributes.join(", ")
+                );
             }
         }
     }
+    println!("Read {row} rows");
+    if bbox.is_some() {
+        println!("{matched} points in the bbox");
+    }
     println!("Done!");
+    Ok(())
 }
@@ -0,0 +1,153 @@
+// thinning out points for quick previews, either randomly by rate or down to an exact count
+// (seeded, so the same seed on the same file always gives the same points)
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use las::Point;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::error::{QuafferError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sampling {
+    // every point that gets past the filters
+    #[default]
+    All,
+    // keep each point with this probability (0-1]
+    Rate(f64),
+    // keep exactly this many points (or all of them if there are fewer),
+    // picked uniformly from the whole file instead of just the first N
+    Random(usize),
+}
+
+impl Sampling {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Sampling::Rate(rate) if !(*rate > 0.0 && *rate <= 1.0) => {
+                Err(QuafferError::InvalidInput(format!(
+                    "sample rate must be above 0 and at most 1, got {rate}"
+                )))
+            }
+            Sampling::Random(0) => Err(QuafferError::InvalidInput(
+                "random sample size must be greater than zero".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// use the given seed, or make one up (and say what it was so the run can be repeated)
+pub fn pick_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        println!("Sampling with seed {seed} (pass --seed {seed} to get the same points again)");
+        seed
+    })
+}
+
+pub struct Sampler {
+    sampling: Sampling,
+    rng: SmallRng,
+    // points offered so far, for the reservoir
+    seen: u64,
+    reservoir: Vec<(i64, Point)>,
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling, seed: u64) -> Self {
+        let capacity = match sampling {
+            Sampling::Random(size) => size,
+            _ => 0,
+        };
+        Sampler {
+            sampling,
+            rng: SmallRng::seed_from_u64(seed),
+            seen: 0,
+            reservoir: Vec::with_capacity(capacity),
+        }
+    }
+
+    // hands the point back if it should be written out right away,
+    // reservoir sampled points are held onto until `finish`
+    pub fn offer(&mut self, fid: i64, pnt: Point) -> Option<(i64, Point)> {
+        match self.sampling {
+            Sampling::All => Some((fid, pnt)),
+            Sampling::Rate(rate) => self.rng.random_bool(rate).then_some((fid, pnt)),
+            Sampling::Random(size) => {
+                // reservoir sampling (algorithm R), every point ends up with a size/seen chance
+                if self.reservoir.len() < size {
+                    self.reservoir.push((fid, pnt));
+                } else {
+                    let slot = self.rng.random_range(0..=self.seen) as usize;
+                    if slot < size {
+                        self.reservoir[slot] = (fid, pnt);
+                    }
+                }
+                self.seen += 1;
+                None
+            }
+        }
+    }
+
+    // whatever's left in the reservoir, back in file order
+    pub fn finish(mut self) -> Vec<(i64, Point)> {
+        self.reservoir.sort_by_key(|(fid, _)| *fid);
+        self.reservoir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fids kept out of `count` points, counting both the ones handed straight back
+    // and what's left in the reservoir
+    fn sample(sampling: Sampling, seed: u64, count: i64) -> Vec<i64> {
+        let mut sampler = Sampler::new(sampling, seed);
+        let mut kept: Vec<i64> = (0..count)
+            .filter_map(|fid| sampler.offer(fid, Point::default()))
+            .map(|(fid, _)| fid)
+            .collect();
+        kept.extend(sampler.finish().into_iter().map(|(fid, _)| fid));
+        kept
+    }
+
+    #[test]
+    fn reservoir_is_repeatable_with_a_seed() {
+        let kept = sample(Sampling::Random(10), 42, 1000);
+        assert_eq!(kept, sample(Sampling::Random(10), 42, 1000));
+        assert_ne!(kept, sample(Sampling::Random(10), 43, 1000));
+        assert_eq!(kept.len(), 10);
+        // in file order, no repeats
+        assert!(kept.windows(2).all(|pair| pair[0] < pair[1]));
+        // picked from the whole file, not just the start
+        assert!(kept.iter().any(|fid| *fid >= 10));
+    }
+
+    #[test]
+    fn small_files_keep_every_point() {
+        assert_eq!(sample(Sampling::Random(10), 7, 4), [0, 1, 2, 3]);
+        assert_eq!(sample(Sampling::All, 7, 4), [0, 1, 2, 3]);
+        assert_eq!(sample(Sampling::Rate(1.0), 7, 4), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn rate_is_repeatable_with_a_seed() {
+        let kept = sample(Sampling::Rate(0.25), 42, 1000);
+        assert_eq!(kept, sample(Sampling::Rate(0.25), 42, 1000));
+        assert!((150..350).contains(&kept.len()), "kept {}", kept.len());
+    }
+
+    #[test]
+    fn bad_samplings_are_rejected() {
+        assert!(Sampling::Rate(0.0).validate().is_err());
+        assert!(Sampling::Rate(1.5).validate().is_err());
+        assert!(Sampling::Rate(f64::NAN).validate().is_err());
+        assert!(Sampling::Random(0).validate().is_err());
+        assert!(Sampling::Rate(0.5).validate().is_ok());
+        assert!(Sampling::Random(1).validate().is_ok());
+    }
+}
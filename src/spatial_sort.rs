@@ -0,0 +1,280 @@
+// reordering points along a space-filling curve before they get chunked into row groups,
+// so each row group covers a compact patch instead of a stripe across the whole tile
+// (which is what makes bbox row group skipping worth anything)
+use std::fmt;
+use std::str::FromStr;
+
+use las::Point;
+
+use crate::bbox::Bbox;
+use crate::error::QuafferError;
+
+// cells per axis are 2^bits, 16 bits keeps the keys in a u32 per axis
+const CURVE_BITS: u32 = 16;
+// the coarse grid for grid-time, 256x256 cells over the tile
+const GRID_BITS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpatialSort {
+    // file order
+    #[default]
+    None,
+    Hilbert,
+    // z-order, cheaper keys but jumpier than hilbert
+    Morton,
+    // hilbert over a coarse grid, then gps_time within each cell
+    // (keeps flight lines together inside a cell)
+    GridTime,
+}
+
+impl FromStr for SpatialSort {
+    type Err = QuafferError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(SpatialSort::None),
+            "hilbert" => Ok(SpatialSort::Hilbert),
+            "morton" | "z-order" => Ok(SpatialSort::Morton),
+            "grid-time" => Ok(SpatialSort::GridTime),
+            other => Err(QuafferError::InvalidInput(format!(
+                "unknown sort `{other}`, expected none, hilbert, morton or grid-time"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SpatialSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpatialSort::None => write!(f, "none"),
+            SpatialSort::Hilbert => write!(f, "hilbert"),
+            SpatialSort::Morton => write!(f, "morton"),
+            SpatialSort::GridTime => write!(f, "grid-time"),
+        }
+    }
+}
+
+// which of 2^bits cells along an axis `value` falls in
+fn cell(value: f64, min: f64, max: f64, bits: u32) -> u32 {
+    if max <= min {
+        return 0;
+    }
+    let cells = ((1u64 << bits) - 1) as f64;
+    (((value - min) / (max - min)) * cells)
+        .round()
+        .clamp(0.0, cells) as u32
+}
+
+// distance along a hilbert curve filling a 2^bits x 2^bits grid
+fn hilbert_index(bits: u32, mut x: u32, mut y: u32) -> u64 {
+    let n = 1u32 << bits;
+    let mut index = 0u64;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        index += u64::from(s) * u64::from(s) * u64::from((3 * rx) ^ ry);
+        // rotate the quadrant so the curve stays connected
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+// spread the low 16 bits of `value` out to every other bit
+fn spread_bits(value: u32) -> u64 {
+    let mut v = u64::from(value & 0xffff);
+    v = (v | (v << 8)) & 0x00ff_00ff;
+    v = (v | (v << 4)) & 0x0f0f_0f0f;
+    v = (v | (v << 2)) & 0x3333_3333;
+    (v | (v << 1)) & 0x5555_5555
+}
+
+fn morton_index(x: u32, y: u32) -> u64 {
+    spread_bits(x) | (spread_bits(y) << 1)
+}
+
+// holds every kept point until the tile's been read, then hands them back in curve order
+// (the points themselves are the memory cost, sorting only adds a small key per point)
+pub struct SpatialSorter {
+    sort: SpatialSort,
+    bounds: Option<Bbox>,
+    points: Vec<(i64, Point)>,
+}
+
+impl SpatialSorter {
+    // None when there's no sorting to do, so points can go straight out
+    pub fn new(sort: SpatialSort) -> Option<Self> {
+        (sort != SpatialSort::None).then(|| SpatialSorter {
+            sort,
+            bounds: None,
+            points: Vec::new(),
+        })
+    }
+
+    pub fn push(&mut self, fid: i64, pnt: Point) {
+        self.bounds = Some(Bbox::extend(self.bounds, pnt.x, pnt.y));
+        self.points.push((fid, pnt));
+    }
+
+    pub fn finish(mut self) -> Vec<(i64, Point)> {
+        let Some(bounds) = self.bounds else {
+            return self.points;
+        };
+        let bits = match self.sort {
+            SpatialSort::GridTime => GRID_BITS,
+            _ => CURVE_BITS,
+        };
+        // sort small (curve key, time, fid, position) entries rather than the points themselves,
+        // then move the points into that order in place, so sorting costs 32 bytes a point
+        // on top of the points instead of a second copy of them
+        let mut order: Vec<(u64, f64, i64, usize)> = self
+            .points
+            .iter()
+            .enumerate()
+            .map(|(position, (fid, pnt))| {
+                let x = cell(pnt.x, bounds.minx, bounds.maxx, bits);
+                let y = cell(pnt.y, bounds.miny, bounds.maxy, bits);
+                let (key, time) = match self.sort {
+                    SpatialSort::None => (0, 0.0),
+                    SpatialSort::Hilbert => (hilbert_index(bits, x, y), 0.0),
+                    SpatialSort::Morton => (morton_index(x, y), 0.0),
+                    SpatialSort::GridTime => {
+                        (hilbert_index(bits, x, y), pnt.gps_time.unwrap_or_default())
+                    }
+                };
+                (key, time, *fid, position)
+            })
+            .collect();
+        // fid breaks ties so the same input always sorts the same way
+        order.sort_unstable_by(|(a_key, a_time, a_fid, _), (b_key, b_time, b_fid, _)| {
+            a_key
+                .cmp(b_key)
+                .then(a_time.total_cmp(b_time))
+                .then(a_fid.cmp(b_fid))
+        });
+        let mut order: Vec<usize> = order.into_iter().map(|(.., position)| position).collect();
+        apply_order(&mut self.points, &mut order);
+        self.points
+    }
+}
+
+// rearrange `items` so items[i] ends up as what was at order[i], following each cycle of
+// the permutation with swaps (`order` is used up marking what's been placed)
+fn apply_order<T>(items: &mut [T], order: &mut [usize]) {
+    for start in 0..items.len() {
+        let mut current = start;
+        while order[current] != current {
+            let next = order[current];
+            order[current] = current;
+            if next == start {
+                break;
+            }
+            items.swap(current, next);
+            current = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cells next to each other along the curve should be next to each other on the grid
+    fn is_adjacent(a: (u32, u32), b: (u32, u32)) -> bool {
+        a.0.abs_diff(b.0) + a.1.abs_diff(b.1) == 1
+    }
+
+    #[test]
+    fn hilbert_visits_every_cell_once_in_adjacent_steps() {
+        assert_eq!(
+            [(0, 0), (0, 1), (1, 1), (1, 0)].map(|(x, y)| hilbert_index(1, x, y)),
+            [0, 1, 2, 3]
+        );
+        let bits = 3;
+        let side = 1u32 << bits;
+        let mut path = vec![None; (side * side) as usize];
+        for x in 0..side {
+            for y in 0..side {
+                let index = hilbert_index(bits, x, y) as usize;
+                assert!(path[index].is_none(), "cell {index} visited twice");
+                path[index] = Some((x, y));
+            }
+        }
+        let path: Vec<(u32, u32)> = path.into_iter().map(Option::unwrap).collect();
+        assert_eq!(path[0], (0, 0));
+        assert!(path.windows(2).all(|step| is_adjacent(step[0], step[1])));
+    }
+
+    #[test]
+    fn morton_interleaves_bits() {
+        assert_eq!(morton_index(0, 0), 0);
+        assert_eq!(morton_index(1, 0), 1);
+        assert_eq!(morton_index(0, 1), 2);
+        assert_eq!(morton_index(3, 3), 15);
+        assert_eq!(morton_index(0b101, 0b010), 0b011001);
+        assert_eq!(morton_index(0xffff, 0), 0x5555_5555);
+        assert_eq!(morton_index(0xffff, 0xffff), 0xffff_ffff);
+    }
+
+    #[test]
+    fn cells_cover_the_bounds() {
+        assert_eq!(cell(0.0, 0.0, 10.0, 8), 0);
+        assert_eq!(cell(10.0, 0.0, 10.0, 8), 255);
+        assert_eq!(cell(-5.0, 0.0, 10.0, 8), 0);
+        assert_eq!(cell(15.0, 0.0, 10.0, 8), 255);
+        // every point in the same place
+        assert_eq!(cell(3.0, 3.0, 3.0, 8), 0);
+    }
+
+    #[test]
+    fn order_is_applied_in_place() {
+        let mut items = vec!['a', 'b', 'c', 'd', 'e'];
+        let mut order = vec![3, 0, 4, 1, 2];
+        apply_order(&mut items, &mut order);
+        assert_eq!(items, ['d', 'a', 'e', 'b', 'c']);
+    }
+
+    #[test]
+    fn sorted_points_follow_the_curve_and_keep_their_fids() {
+        // a 4x4 grid of points, pushed in a scrambled order
+        let mut cells: Vec<(u32, u32)> = (0..4).flat_map(|x| (0..4).map(move |y| (x, y))).collect();
+        cells.sort_by_key(|(x, y)| (x * 7 + y * 5) % 16);
+        let sort = |sort: SpatialSort| {
+            let mut sorter = SpatialSorter::new(sort).unwrap();
+            for (fid, (x, y)) in cells.iter().enumerate() {
+                let pnt = Point {
+                    x: f64::from(*x),
+                    y: f64::from(*y),
+                    ..Default::default()
+                };
+                sorter.push(fid as i64, pnt);
+            }
+            sorter.finish()
+        };
+
+        let sorted = sort(SpatialSort::Hilbert);
+        assert_eq!(sorted, sort(SpatialSort::Hilbert));
+        let path: Vec<(u32, u32)> = sorted
+            .iter()
+            .map(|(_, pnt)| (pnt.x as u32, pnt.y as u32))
+            .collect();
+        assert_eq!(path[0], (0, 0));
+        assert!(path.windows(2).all(|step| is_adjacent(step[0], step[1])));
+        for (fid, pnt) in &sorted {
+            assert_eq!(cells[*fid as usize], (pnt.x as u32, pnt.y as u32));
+        }
+
+        let morton: Vec<(u32, u32)> = sort(SpatialSort::Morton)
+            .iter()
+            .map(|(_, pnt)| (pnt.x as u32, pnt.y as u32))
+            .collect();
+        assert_eq!(&morton[..4], [(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+}
@@ -0,0 +1,400 @@
+// QA numbers for a geoparquet point file: per-column distributions, class and return
+// breakdowns, density and a z histogram
+// streams the file twice, once for ranges/moments/counts and once to fill the histograms
+// the percentiles come from, so memory stays flat no matter how big the file is
+use std::collections::BTreeMap;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::UInt8Type;
+use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_schema::DataType;
+use serde_json::{Value, json};
+
+use crate::error::{QuafferError, Result};
+use crate::read_parq::{Coords, Numbers, open_points};
+
+// fine bins for percentiles (so they're within range/4096 of exact)
+const PERCENTILE_BINS: usize = 4096;
+const PERCENTILES: [f64; 7] = [1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0];
+const Z_HISTOGRAM_BINS: usize = 20;
+const HISTOGRAM_BAR_WIDTH: usize = 50;
+
+#[derive(Debug, Default)]
+struct ColumnStats {
+    count: u64,
+    nulls: u64,
+    min: f64,
+    max: f64,
+    // running mean and sum of squared differences (Welford), stable for big coords
+    mean: f64,
+    m2: f64,
+    // filled on the second pass, once min/max are known
+    histogram: Vec<u64>,
+}
+
+impl ColumnStats {
+    fn add(&mut self, value: Option<f64>) {
+        let Some(value) = value.filter(|value| value.is_finite()) else {
+            self.nulls += 1;
+            return;
+        };
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    fn bin(&self, value: f64, bins: usize) -> usize {
+        if self.max <= self.min {
+            return 0;
+        }
+        let bin = ((value - self.min) / (self.max - self.min) * bins as f64) as usize;
+        bin.min(bins - 1)
+    }
+
+    fn add_to_histogram(&mut self, value: Option<f64>) {
+        let Some(value) = value.filter(|value| value.is_finite()) else {
+            return;
+        };
+        if self.histogram.is_empty() {
+            self.histogram = vec![0; PERCENTILE_BINS];
+        }
+        let bin = self.bin(value, PERCENTILE_BINS);
+        self.histogram[bin] += 1;
+    }
+
+    fn stddev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+
+    // interpolated within the bin the percentile lands in
+    fn percentile(&self, percent: f64) -> f64 {
+        let target = percent / 100.0 * self.count as f64;
+        let width = (self.max - self.min) / self.histogram.len().max(1) as f64;
+        let mut seen = 0.0;
+        for (bin, count) in self.histogram.iter().enumerate() {
+            let count = *count as f64;
+            if count > 0.0 && seen + count >= target {
+                let within = (target - seen) / count;
+                return self.min + (bin as f64 + within) * width;
+            }
+            seen += count;
+        }
+        self.max
+    }
+
+    fn to_json(&self, name: &str) -> Value {
+        if self.count == 0 {
+            return json!({ "column": name, "count": 0, "nulls": self.nulls });
+        }
+        let percentiles: serde_json::Map<String, Value> = PERCENTILES
+            .iter()
+            .map(|percent| (format!("p{percent}"), json!(self.percentile(*percent))))
+            .collect();
+        json!({
+            "column": name,
+            "count": self.count,
+            "nulls": self.nulls,
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "stddev": self.stddev(),
+            "percentiles": percentiles,
+        })
+    }
+}
+
+fn is_numeric(data_type: &DataType) -> bool {
+    data_type.is_integer() || data_type.is_floating()
+}
+
+// the classification label for each row, from the code dictionary or legacy strings
+fn class_labels(batch: &RecordBatch) -> Option<Vec<Option<String>>> {
+    let column = batch.column_by_name("classification")?;
+    if let Some(dictionary) = column.as_dictionary_opt::<UInt8Type>() {
+        let labels = dictionary.values().as_string_opt::<i32>()?;
+        return Some(
+            dictionary
+                .keys()
+                .iter()
+                .map(|key| key.map(|key| labels.value(key as usize).to_string()))
+                .collect(),
+        );
+    }
+    let labels = column.as_string_opt::<i32>()?;
+    Some(
+        labels
+            .iter()
+            .map(|label| label.map(str::to_string))
+            .collect(),
+    )
+}
+
+#[derive(Debug, Default)]
+struct FileStats {
+    points: u64,
+    x: ColumnStats,
+    y: ColumnStats,
+    z: ColumnStats,
+    // in schema order
+    columns: Vec<(String, ColumnStats)>,
+    classes: BTreeMap<String, u64>,
+    // return number -> count, plus single/first/last breakdowns
+    returns: BTreeMap<u64, u64>,
+    single_returns: u64,
+    first_of_many: u64,
+    last_of_many: u64,
+    z_histogram: Vec<u64>,
+}
+
+impl FileStats {
+    // first pass, everything but the histograms
+    fn add_batch(&mut self, batch: &RecordBatch, geometry_column: &str) -> Result<()> {
+        let coords = Coords::from_batch(batch, geometry_column)?;
+        // a 2D point file keeps z in its own column
+        let z_column = Numbers::from_batch(batch, "z")?;
+        for idx in 0..batch.num_rows() {
+            let (x, y, z) = coords.xyz(idx);
+            self.x.add(Some(x));
+            self.y.add(Some(y));
+            self.z
+                .add(z.or_else(|| z_column.as_ref().and_then(|column| column.float(idx))));
+        }
+        self.points += batch.num_rows() as u64;
+
+        for (name, stats) in self.columns.iter_mut() {
+            let Some(column) = Numbers::from_batch(batch, name)? else {
+                continue;
+            };
+            for idx in 0..batch.num_rows() {
+                stats.add(column.float(idx));
+            }
+        }
+
+        if let Some(labels) = class_labels(batch) {
+            for label in labels {
+                let label = label.unwrap_or_else(|| "null".to_string());
+                *self.classes.entry(label).or_default() += 1;
+            }
+        }
+
+        let return_number = Numbers::from_batch(batch, "return_number")?;
+        let number_of_returns = Numbers::from_batch(batch, "number_of_returns")?;
+        if let Some(return_number) = &return_number {
+            for idx in 0..batch.num_rows() {
+                let Some(number) = return_number.int(idx) else {
+                    continue;
+                };
+                *self.returns.entry(number).or_default() += 1;
+                let Some(of) = number_of_returns
+                    .as_ref()
+                    .and_then(|column| column.int(idx))
+                else {
+                    continue;
+                };
+                match (number, of) {
+                    (_, 1) => self.single_returns += 1,
+                    (1, _) => self.first_of_many += 1,
+                    (number, of) if number == of => self.last_of_many += 1,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // second pass, with ranges known
+    fn add_batch_histograms(&mut self, batch: &RecordBatch, geometry_column: &str) -> Result<()> {
+        let coords = Coords::from_batch(batch, geometry_column)?;
+        let z_column = Numbers::from_batch(batch, "z")?;
+        for idx in 0..batch.num_rows() {
+            let (x, y, z) = coords.xyz(idx);
+            let z = z.or_else(|| z_column.as_ref().and_then(|column| column.float(idx)));
+            self.x.add_to_histogram(Some(x));
+            self.y.add_to_histogram(Some(y));
+            self.z.add_to_histogram(z);
+            if let Some(z) = z.filter(|z| z.is_finite())
+                && let Some(count) = self.z_histogram.get_mut(self.z.bin(z, Z_HISTOGRAM_BINS))
+            {
+                *count += 1;
+            }
+        }
+        for (name, stats) in self.columns.iter_mut() {
+            let Some(column) = Numbers::from_batch(batch, name)? else {
+                continue;
+            };
+            for idx in 0..batch.num_rows() {
+                stats.add_to_histogram(column.float(idx));
+            }
+        }
+        Ok(())
+    }
+
+    fn density(&self) -> Option<f64> {
+        let area = (self.x.max - self.x.min) * (self.y.max - self.y.min);
+        (area > 0.0).then(|| self.points as f64 / area)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut columns = vec![self.x.to_json("x"), self.y.to_json("y")];
+        if self.z.count > 0 {
+            columns.push(self.z.to_json("z"));
+        }
+        columns.extend(self.columns.iter().map(|(name, stats)| stats.to_json(name)));
+        let z_width = (self.z.max - self.z.min) / Z_HISTOGRAM_BINS as f64;
+        let z_histogram: Vec<Value> = self
+            .z_histogram
+            .iter()
+            .enumerate()
+            .map(|(bin, count)| {
+                json!({
+                    "from": self.z.min + bin as f64 * z_width,
+                    "to": self.z.min + (bin + 1) as f64 * z_width,
+                    "count": count,
+                })
+            })
+            .collect();
+        json!({
+            "points": self.points,
+            // over the xy bounding box, in CRS units (usually m²)
+            "points_per_square_unit": self.density(),
+            "columns": columns,
+            "classification": self.classes,
+            "returns": {
+                "by_return_number": self.returns,
+                "single": self.single_returns,
+                "first_of_many": self.first_of_many,
+                "last_of_many": self.last_of_many,
+            },
+            "z_histogram": z_histogram,
+        })
+    }
+
+    fn print_table(&self) {
+        println!("{} points", self.points);
+        match self.density() {
+            Some(density) => println!("{density:.3} points per square unit (over the xy bounds)"),
+            None => println!("no area to work out a density over"),
+        }
+        println!();
+        let header = ["column", "count", "nulls", "min", "max", "mean", "stddev"];
+        let percentile_header: Vec<String> = PERCENTILES
+            .iter()
+            .map(|percent| format!("p{percent}"))
+            .collect();
+        println!(
+            "{:<28} {:>12} {:>10} {:>14} {:>14} {:>14} {:>14} {}",
+            header[0],
+            header[1],
+            header[2],
+            header[3],
+            header[4],
+            header[5],
+            header[6],
+            percentile_header
+                .iter()
+                .map(|name| format!("{name:>14}"))
+                .collect::<String>()
+        );
+        let mut rows = vec![("x", &self.x), ("y", &self.y)];
+        if self.z.count > 0 {
+            rows.push(("z", &self.z));
+        }
+        rows.extend(
+            self.columns
+                .iter()
+                .map(|(name, stats)| (name.as_str(), stats)),
+        );
+        for (name, stats) in rows {
+            if stats.count == 0 {
+                println!("{name:<28} {:>12} {:>10}", 0, stats.nulls);
+                continue;
+            }
+            let percentiles: String = PERCENTILES
+                .iter()
+                .map(|percent| format!("{:>14.3}", stats.percentile(*percent)))
+                .collect();
+            println!(
+                "{name:<28} {:>12} {:>10} {:>14.3} {:>14.3} {:>14.3} {:>14.3} {percentiles}",
+                stats.count,
+                stats.nulls,
+                stats.min,
+                stats.max,
+                stats.mean,
+                stats.stddev()
+            );
+        }
+
+        if !self.classes.is_empty() {
+            println!("\nclassification:");
+            for (label, count) in &self.classes {
+                let percent = *count as f64 / self.points.max(1) as f64 * 100.0;
+                println!("  {label:<28} {count:>12} ({percent:.2}%)");
+            }
+        }
+        if !self.returns.is_empty() {
+            println!("\nreturns:");
+            for (number, count) in &self.returns {
+                println!("  return {number:<21} {count:>12}");
+            }
+            println!("  {:<28} {:>12}", "single", self.single_returns);
+            println!("  {:<28} {:>12}", "first of many", self.first_of_many);
+            println!("  {:<28} {:>12}", "last of many", self.last_of_many);
+        }
+        if self.z.count > 0 {
+            println!("\nz histogram:");
+            let z_width = (self.z.max - self.z.min) / Z_HISTOGRAM_BINS as f64;
+            let biggest = self.z_histogram.iter().copied().max().unwrap_or(1).max(1);
+            for (bin, count) in self.z_histogram.iter().enumerate() {
+                let from = self.z.min + bin as f64 * z_width;
+                let bar = "#".repeat((*count as usize * HISTOGRAM_BAR_WIDTH) / biggest as usize);
+                println!("  {from:>12.3} {count:>12} {bar}");
+            }
+        }
+    }
+}
+
+pub fn stats(filepath: &str, as_json: bool) -> Result<()> {
+    let first_pass = open_points(filepath, &[])?;
+    let geometry_column = first_pass.geometry_column;
+    let schema = first_pass.reader.schema();
+    let mut stats = FileStats {
+        columns: schema
+            .fields()
+            .iter()
+            .filter(|field| field.name() != &geometry_column && field.name() != "z")
+            .filter(|field| is_numeric(field.data_type()))
+            .map(|field| (field.name().clone(), ColumnStats::default()))
+            .collect(),
+        ..Default::default()
+    };
+    for batch in first_pass.reader {
+        stats.add_batch(&batch?, &geometry_column)?;
+    }
+    // no z at all means no z histogram
+    if stats.z.count > 0 {
+        stats.z_histogram = vec![0; Z_HISTOGRAM_BINS];
+    }
+    for batch in open_points(filepath, &[])?.reader {
+        stats.add_batch_histograms(&batch?, &geometry_column)?;
+    }
+
+    if as_json {
+        let text = serde_json::to_string_pretty(&stats.to_json()).map_err(|err| {
+            QuafferError::InvalidInput(format!("couldn't write stats as JSON: {err}"))
+        })?;
+        println!("{text}");
+    } else {
+        stats.print_table();
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+// delimited text point clouds: .xyz, .csv, .pts and friends.
+// one point per line, columns picked from a header row, --csv-columns, or x,y,z
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use las::Point;
+
+use crate::error::{QuafferError, Result};
+use crate::laz_to_gpq::{PointAttributes, WKTStringTransform};
+use crate::point_source::{PointField, PointSource, new_point, set_field};
+
+// how to split up the lines of a text file
+#[derive(Debug, Clone, Default)]
+pub struct CsvOptions {
+    // what each column holds, in order (overrides a header row)
+    pub columns: Vec<PointField>,
+    // None sniffs it from the first line (comma, semicolon, tab, else whitespace)
+    pub delimiter: Option<char>,
+}
+
+pub struct XyzSource {
+    path: PathBuf,
+    columns: Vec<PointField>,
+    // None splits on runs of whitespace
+    delimiter: Option<char>,
+    // lines before the first point (PTS count line, header row)
+    skip_lines: usize,
+    number_of_points: u64,
+}
+
+// comments and blank lines don't count as points
+fn is_data_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#') && !line.starts_with("//")
+}
+
+fn split_line(line: &str, delimiter: Option<char>) -> Vec<&str> {
+    match delimiter {
+        Some(delimiter) => line
+            .split(delimiter)
+            .map(|token| token.trim().trim_matches('"'))
+            .collect(),
+        None => line.split_whitespace().collect(),
+    }
+}
+
+fn sniff_delimiter(line: &str) -> Option<char> {
+    [',', ';', '\t']
+        .into_iter()
+        .find(|delimiter| line.contains(*delimiter))
+}
+
+impl XyzSource {
+    // one pass over the file up front to work out the layout and count the points
+    pub fn open(path: &Path, options: &CsvOptions) -> Result<Self> {
+        let name = path.display();
+        let reader = BufReader::new(File::open(path)?);
+        let mut delimiter = options.delimiter.filter(|delimiter| *delimiter != ' ');
+        let mut columns = options.columns.clone();
+        let mut skip_lines = 0;
+        let mut number_of_points: u64 = 0;
+        let mut seen_first_line = false;
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            if !is_data_line(&line) {
+                continue;
+            }
+            if number_of_points > 0 {
+                number_of_points += 1;
+                continue;
+            }
+            if options.delimiter.is_none() {
+                delimiter = sniff_delimiter(&line);
+            }
+            let tokens = split_line(line.trim(), delimiter);
+            // PTS files start with the point count on a line of its own
+            if !seen_first_line && tokens.len() == 1 && tokens[0].parse::<u64>().is_ok() {
+                seen_first_line = true;
+                skip_lines = idx + 1;
+                continue;
+            }
+            seen_first_line = true;
+            if tokens.iter().any(|token| token.parse::<f64>().is_err()) {
+                // a header row, only used if the columns weren't given
+                if columns.is_empty() {
+                    columns = tokens
+                        .iter()
+                        .map(|token| {
+                            token.parse().unwrap_or_else(|_| {
+                                eprintln!("WARNING: {name}: ignoring unknown column `{token}`");
+                                PointField::Skip
+                            })
+                        })
+                        .collect();
+                }
+                skip_lines = idx + 1;
+                continue;
+            }
+            if columns.is_empty() {
+                columns = vec![PointField::X, PointField::Y, PointField::Z];
+                if tokens.len() > columns.len() {
+                    eprintln!(
+                        "WARNING: {name} has {} columns but no header, reading the first 3 \
+                        as x,y,z and ignoring the rest (pass --csv-columns to use them)",
+                        tokens.len()
+                    );
+                }
+            }
+            skip_lines = idx;
+            number_of_points = 1;
+        }
+        for (required, label) in [(PointField::X, "x"), (PointField::Y, "y")] {
+            if !columns.contains(&required) {
+                return Err(QuafferError::InvalidInput(format!(
+                    "{name} has no {label} column, pass --csv-columns to say which one it is"
+                )));
+            }
+        }
+        Ok(XyzSource {
+            path: path.to_path_buf(),
+            columns,
+            delimiter,
+            skip_lines,
+            number_of_points,
+        })
+    }
+}
+
+impl PointSource for XyzSource {
+    // text files don't carry a CRS, it has to come from --source-crs
+    fn crs(&self) -> Result<Option<WKTStringTransform>> {
+        Ok(None)
+    }
+
+    fn number_of_points(&self) -> u64 {
+        self.number_of_points
+    }
+
+    fn attributes(&self) -> PointAttributes {
+        PointField::attributes(&self.columns)
+    }
+
+    fn read_points(&mut self, on_point: &mut dyn FnMut(Point) -> Result<bool>) -> Result<()> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        for (idx, line) in reader.lines().enumerate().skip(self.skip_lines) {
+            let line = line?;
+            if !is_data_line(&line) {
+                continue;
+            }
+            let tokens = split_line(line.trim(), self.delimiter);
+            if tokens.len() < self.columns.len() {
+                return Err(QuafferError::InvalidInput(format!(
+                    "{} line {}: expected {} values, found {}",
+                    self.path.display(),
+                    idx + 1,
+                    self.columns.len(),
+                    tokens.len()
+                )));
+            }
+            let mut point = new_point();
+            for (field, token) in self.columns.iter().zip(tokens) {
+                if *field == PointField::Skip {
+                    continue;
+                }
+                let value = token.parse::<f64>().map_err(|_| {
+                    QuafferError::InvalidInput(format!(
+                        "{} line {}: `{token}` isn't a number",
+                        self.path.display(),
+                        idx + 1
+                    ))
+                })?;
+                set_field(&mut point, *field, value);
+            }
+            if !on_point(point)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}